@@ -0,0 +1,274 @@
+use crate::hwmon;
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// directory of drm devices
+const DRM: &str = "/sys/class/drm";
+
+/// properties of card which must match. first card is used if all are `None`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AmdgpuSelector {
+    pub pci_bus_id: Option<String>,
+    /// index of card among amdgpu cards ordered by `cardN`
+    pub index: Option<u32>,
+}
+
+/// amdgpu card found in sysfs
+pub struct AmdgpuCard {
+    /// `/sys/class/drm/cardN/device`
+    device: PathBuf,
+    pci_bus_id: String,
+    hwmon: PathBuf,
+}
+
+#[derive(Debug, Error)]
+pub enum AmdgpuError {
+    #[error("{0}")]
+    Io(io::Error),
+    #[error("no amdgpu cards")]
+    NoCards,
+    #[error("not found {0}")]
+    NotFound(AmdgpuSelector),
+    #[error("{0:?} has no hwmon")]
+    NoHwmon(PathBuf),
+    #[error("{card} has no {name}")]
+    NoSensor { card: String, name: String },
+}
+
+impl AmdgpuCard {
+    /// find card matching `selector`
+    pub fn find(selector: &AmdgpuSelector) -> Result<Self, AmdgpuError> {
+        Self::find_in(Path::new(DRM), selector)
+    }
+
+    fn find_in(drm: &Path, selector: &AmdgpuSelector) -> Result<Self, AmdgpuError> {
+        let cards = Self::cards(drm)?;
+        if cards.is_empty() {
+            return Err(AmdgpuError::NoCards);
+        }
+
+        let (device, pci_bus_id) = cards
+            .into_iter()
+            .enumerate()
+            .find(|(index, (_, pci_bus_id))| {
+                selector.index.is_none_or(|i| i as usize == *index)
+                    && selector
+                        .pci_bus_id
+                        .as_ref()
+                        .is_none_or(|id| id.eq_ignore_ascii_case(pci_bus_id))
+            })
+            .map(|(_, card)| card)
+            .ok_or_else(|| AmdgpuError::NotFound(selector.clone()))?;
+
+        let hwmon = hwmon::find(&device)?.ok_or_else(|| AmdgpuError::NoHwmon(device.clone()))?;
+
+        Ok(Self {
+            device,
+            pci_bus_id,
+            hwmon,
+        })
+    }
+
+    /// devices of amdgpu cards with their PCI bus ids, ordered by `cardN`
+    fn cards(drm: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+        let mut cards = Vec::new();
+
+        for entry in fs::read_dir(drm)? {
+            let name = entry?.file_name();
+            // connectors like `card0-DP-1` are skipped
+            let Some(number) = name
+                .to_string_lossy()
+                .strip_prefix("card")
+                .and_then(|number| number.parse::<u32>().ok())
+            else {
+                continue;
+            };
+
+            let device = drm.join(&name).join("device");
+            let driver = fs::read_link(device.join("driver")).ok();
+            if !driver.is_some_and(|driver| driver.ends_with("amdgpu")) {
+                continue;
+            }
+
+            let pci_bus_id = fs::canonicalize(&device)?
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            log::debug!("Found amdgpu card {pci_bus_id} (card{number})");
+
+            cards.push((number, device, pci_bus_id));
+        }
+        cards.sort_by_key(|&(number, ..)| number);
+
+        Ok(cards
+            .into_iter()
+            .map(|(_, device, pci_bus_id)| (device, pci_bus_id))
+            .collect())
+    }
+
+    fn sensor(&self, path: PathBuf, name: &str) -> Result<PathBuf, AmdgpuError> {
+        if path.exists() {
+            Ok(path)
+        } else {
+            Err(AmdgpuError::NoSensor {
+                card: self.to_string(),
+                name: name.to_string(),
+            })
+        }
+    }
+
+    /// `tempN_input` with `label` (`edge`, `junction` or `mem`). millidegrees
+    pub fn temperature(&self, label: &str) -> Result<PathBuf, AmdgpuError> {
+        hwmon::temperatures(&self.hwmon)?
+            .into_iter()
+            .find(|(found, _)| found == label)
+            .map(|(_, path)| path)
+            .ok_or_else(|| AmdgpuError::NoSensor {
+                card: self.to_string(),
+                name: format!("{label} temperature"),
+            })
+    }
+
+    /// average power. microwatts
+    pub fn power(&self) -> Result<PathBuf, AmdgpuError> {
+        // newer kernels report `power1_input` instead of `power1_average`
+        let average = self.hwmon.join("power1_average");
+        if average.exists() {
+            return Ok(average);
+        }
+
+        self.sensor(self.hwmon.join("power1_input"), "power1_average")
+    }
+
+    /// percents
+    pub fn busy_percent(&self) -> Result<PathBuf, AmdgpuError> {
+        self.sensor(self.device.join("gpu_busy_percent"), "gpu_busy_percent")
+    }
+
+    /// `pwm1` of card's fan
+    pub fn pwm(&self) -> Result<PathBuf, AmdgpuError> {
+        self.sensor(self.hwmon.join("pwm1"), "pwm1")
+    }
+}
+
+impl From<io::Error> for AmdgpuError {
+    fn from(value: io::Error) -> Self {
+        AmdgpuError::Io(value)
+    }
+}
+
+impl fmt::Display for AmdgpuSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        if let Some(pci_bus_id) = &self.pci_bus_id {
+            write!(f, " pci_bus_id = {pci_bus_id:?}")?;
+        }
+        if let Some(index) = self.index {
+            write!(f, " index = {index}")?;
+        }
+        f.write_str(" }")
+    }
+}
+
+impl fmt::Display for AmdgpuCard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "amdgpu card {}", self.pci_bus_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AmdgpuCard, AmdgpuError, AmdgpuSelector};
+    use crate::{
+        source::{AmdgpuMetric, Source, SourceAmdgpu},
+        test_dir::TestDir,
+    };
+    use std::{fs, os::unix::fs::symlink, path::Path};
+
+    /// create `cardN` in `drm` for PCI device `pci_bus_id` bound to `driver`
+    fn card(root: &Path, number: u32, pci_bus_id: &str, driver: &str) {
+        let device = root.join("devices").join(pci_bus_id);
+        let hwmon = device.join(format!("hwmon/hwmon{number}"));
+        fs::create_dir_all(&hwmon).unwrap();
+        fs::create_dir_all(root.join("drivers").join(driver)).unwrap();
+        symlink(root.join("drivers").join(driver), device.join("driver")).unwrap();
+
+        fs::write(device.join("gpu_busy_percent"), "12\n").unwrap();
+        for (name, value) in [
+            ("temp1_input", "45000\n"),
+            ("temp1_label", "edge\n"),
+            ("temp2_input", "52000\n"),
+            ("temp2_label", "junction\n"),
+            ("temp3_input", "60000\n"),
+            ("temp3_label", "mem\n"),
+            ("power1_input", "35000000\n"),
+            ("pwm1", "80\n"),
+            ("pwm1_enable", "2\n"),
+        ] {
+            fs::write(hwmon.join(name), value).unwrap();
+        }
+
+        let card = root.join(format!("drm/card{number}"));
+        fs::create_dir_all(&card).unwrap();
+        symlink(&device, card.join("device")).unwrap();
+        fs::create_dir_all(root.join(format!("drm/card{number}-DP-1"))).unwrap();
+    }
+
+    #[test]
+    fn find() {
+        let root = TestDir::new("amdgpu");
+        let drm = root.join("drm");
+        card(&root, 0, "0000:00:02.0", "i915");
+        card(&root, 1, "0000:03:00.0", "amdgpu");
+        card(&root, 2, "0000:0a:00.0", "amdgpu");
+
+        let find = |pci_bus_id: Option<&str>, index| {
+            let selector = AmdgpuSelector {
+                pci_bus_id: pci_bus_id.map(String::from),
+                index,
+            };
+            AmdgpuCard::find_in(&drm, &selector).map(|card| card.pci_bus_id)
+        };
+
+        assert_eq!(find(None, None).unwrap(), "0000:03:00.0");
+        assert_eq!(find(None, Some(1)).unwrap(), "0000:0a:00.0");
+        assert_eq!(find(Some("0000:0A:00.0"), None).unwrap(), "0000:0a:00.0");
+        assert_eq!(find(Some("0000:0a:00.0"), Some(1)).unwrap(), "0000:0a:00.0");
+        assert!(matches!(
+            find(Some("0000:0a:00.0"), Some(0)),
+            Err(AmdgpuError::NotFound(_))
+        ));
+        // not amdgpu
+        assert_eq!(
+            find(Some("0000:00:02.0"), None).unwrap_err().to_string(),
+            r#"not found { pci_bus_id = "0000:00:02.0" }"#
+        );
+
+        let card = AmdgpuCard::find_in(&drm, &AmdgpuSelector::default()).unwrap();
+        let hwmon = drm.join("card1/device/hwmon/hwmon1");
+        assert_eq!(card.temperature("edge").unwrap(), hwmon.join("temp1_input"));
+        assert_eq!(card.temperature("mem").unwrap(), hwmon.join("temp3_input"));
+        assert_eq!(
+            card.temperature("hotspot").unwrap_err().to_string(),
+            "amdgpu card 0000:03:00.0 has no hotspot temperature"
+        );
+        assert_eq!(card.power().unwrap(), hwmon.join("power1_input"));
+
+        // microwatts of 1000 W and more have 10 digits
+        fs::write(hwmon.join("power1_input"), "1250000000\n").unwrap();
+        let power = SourceAmdgpu::new(&card, AmdgpuMetric::PowerWatts).unwrap();
+        assert_eq!(
+            power.try_get_temperature().unwrap().celsius().round(),
+            1250.0
+        );
+        assert_eq!(card.pwm().unwrap(), hwmon.join("pwm1"));
+        assert_eq!(
+            card.busy_percent().unwrap(),
+            drm.join("card1/device/gpu_busy_percent")
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 pub struct App {
@@ -9,4 +10,19 @@ pub struct App {
         default_value_t = String::from("/etc/fand/config.toml")
     )]
     pub config: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check results of fan formulas for fixed source values
+    TestFormula {
+        /// TOML or JSON file with cases
+        #[arg(value_name = "CASES")]
+        cases: PathBuf,
+        /// Allowed difference between result and expected power
+        #[arg(short, long, default_value_t = 0.01)]
+        tolerance: f64,
+    },
 }
@@ -2,14 +2,96 @@ use crate::{
     fan::FanPower,
     source::{Source, Temperature},
 };
+use console::Console;
 use deno_core::{
-    error::AnyError as DenoError, v8, Extension, FastString, JsRuntime, RuntimeOptions,
+    anyhow::Context as _,
+    error::{generic_error, AnyError as DenoError, JsError},
+    v8, FsModuleLoader, JsRuntime, RuntimeOptions,
 };
-use std::{cell::RefCell, collections::HashMap, error::Error, rc::Rc};
+use std::{
+    cell::{RefCell, RefMut},
+    collections::{HashMap, VecDeque},
+    env,
+    error::Error,
+    fs,
+    ops::{Deref, DerefMut},
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use stdlib::HelpersState;
+use thiserror::Error;
+use watchdog::Watchdog;
+
+mod console;
+mod stdlib;
+mod watchdog;
+
+/// arguments of compiled formula
+const FORMULA_ARGUMENTS: [&str; 4] = ["sources", "state", "context", "history"];
+/// execution budget of shared script and every file of `scripts`
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct Computed<'a> {
-    formula: String,
+    fan: usize,
+    name: String,
+    /// execution budget of formula
+    timeout: Duration,
+    context: v8::Global<v8::Context>,
+    function: v8::Global<v8::Function>,
+    sources: v8::Global<v8::Object>,
+    state: v8::Global<v8::Object>,
+    history: v8::Global<v8::Object>,
     engine: &'a ComputeEngine,
+    hold: MinHold,
+    /// power applied to fan on previous tick
+    previous: Option<f64>,
+    last_tick: Option<Instant>,
+    /// reason given by formula for last result
+    reason: Option<String>,
+}
+
+/// hour of local wall clock
+fn local_hour() -> i32 {
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    tm.tm_hour
+}
+
+/// result returned by formula
+#[derive(Debug, PartialEq)]
+struct FormulaResult {
+    power: f64,
+    reason: Option<String>,
+    min_hold: Duration,
+}
+
+struct Hold {
+    power: f64,
+    until: Instant,
+    reason: Option<String>,
+}
+
+/// keeps power from dropping while `min_hold` of previous result is not expired
+#[derive(Default)]
+struct MinHold {
+    hold: Option<Hold>,
+}
+
+#[derive(Debug, Error)]
+pub enum ComputedError {
+    #[error("cannot compile formula of {fan} at {line}:{column}: {message}")]
+    Compile {
+        fan: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("formula of {fan} has several statements but no top-level `return`, add `return` before its result")]
+    NoReturn { fan: String },
 }
 
 enum CachedResult<T, E> {
@@ -18,88 +100,461 @@ enum CachedResult<T, E> {
     Err(E),
 }
 
-struct EngineStaticValues {
+/// state of engine shared with callbacks. kept in slot of isolate
+struct EngineState {
     sources: HashMap<String, Rc<dyn Source>>,
     cache: HashMap<String, Temperature>,
+    /// fan whose formula is being computed
+    current_fan: usize,
+    helpers: HelpersState,
+    /// last samples of every source
+    history: HashMap<String, VecDeque<f64>>,
+    history_len: usize,
+    /// `console` state of every fan
+    consoles: HashMap<usize, Console>,
 }
 
+/// runtime of engine with its isolate entered, so engines of one thread can be used interleaved
+struct EnteredRuntime<'a>(RefMut<'a, JsRuntime>);
+
+/// engines created on one thread must be dropped in reverse order of creation
 pub struct ComputeEngine {
+    state: Rc<RefCell<EngineState>>,
+    started: Instant,
+    watchdog: Watchdog,
+    /// execution budget of shared scripts
+    script_timeout: Duration,
     js: RefCell<JsRuntime>,
+    /// globals defined by shared script
+    shared: RefCell<Vec<(String, v8::Global<v8::Value>)>>,
 }
 
-static mut ENGINE_STATIC_VALUES: Option<EngineStaticValues> = None;
-
 impl ComputeEngine {
-    pub fn new(sources: HashMap<String, Rc<dyn Source>>) -> Self {
-        #[allow(static_mut_refs)]
-        unsafe {
-            assert!(ENGINE_STATIC_VALUES.is_none())
-        };
+    /// `history` is count of samples kept for every source
+    pub fn new(sources: HashMap<String, Rc<dyn Source>>, history: usize) -> Self {
+        let state = Rc::new(RefCell::new(EngineState {
+            sources,
+            cache: HashMap::new(),
+            current_fan: 0,
+            helpers: HelpersState::default(),
+            history: HashMap::new(),
+            history_len: history,
+            consoles: HashMap::new(),
+        }));
 
-        unsafe {
-            ENGINE_STATIC_VALUES = Some(EngineStaticValues {
-                sources,
-                cache: HashMap::new(),
-            })
-        };
-
-        let js = JsRuntime::new(RuntimeOptions {
-            extensions: vec![Extension {
-                global_object_middleware: Some(Self::middleware),
-                ..Default::default()
-            }],
+        let mut js = JsRuntime::new(RuntimeOptions {
+            module_loader: Some(Rc::new(FsModuleLoader)),
             ..Default::default()
         });
 
+        // default callback expects contexts created by deno_core only
+        js.v8_isolate()
+            .set_promise_reject_callback(Self::promise_reject_callback);
+        js.v8_isolate().set_slot(state.clone());
+
+        {
+            let scope = &mut js.handle_scope();
+            let global = scope.get_current_context().global(scope);
+            Self::install_globals(scope, global);
+        }
+
+        let watchdog = Watchdog::new(js.v8_isolate().thread_safe_handle());
+
         Self {
+            state,
+            started: Instant::now(),
+            watchdog,
+            script_timeout: SCRIPT_TIMEOUT,
             js: RefCell::new(js),
+            shared: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn js(&self) -> EnteredRuntime<'_> {
+        let mut js = self.js.borrow_mut();
+        unsafe { js.v8_isolate().enter() };
+        EnteredRuntime(js)
+    }
+
+    /// run script in main context and make its globals visible in all formulas
+    ///
+    /// must be called before [`ComputeEngine::create_computed`]
+    pub fn load_shared(&self, script: &str) -> Result<(), DenoError> {
+        self.with_budget("shared script", || {
+            self.share_new_globals(|js| {
+                Self::run_script(&mut js.handle_scope(), "[shared]", script)
+            })
+        })
+    }
+
+    /// load helpers from file. `*.mjs` files are loaded as ES modules and their exports are
+    /// shared, globals of other files are shared like for [`ComputeEngine::load_shared`]
+    ///
+    /// must be called before [`ComputeEngine::create_computed`]
+    pub fn load_file(&self, path: &Path) -> Result<(), DenoError> {
+        let ret = self.with_budget(&format!("{path:?}"), || {
+            if path.extension().is_some_and(|ext| ext == "mjs") {
+                self.load_module(path)
+            } else {
+                fs::read_to_string(path)
+                    .map_err(DenoError::from)
+                    .and_then(|script| {
+                        self.share_new_globals(|js| {
+                            Self::run_script(
+                                &mut js.handle_scope(),
+                                &path.to_string_lossy(),
+                                &script,
+                            )
+                        })
+                    })
+            }
+        });
+
+        ret.with_context(|| format!("cannot load {path:?}"))
+    }
+
+    /// run `f` which must finish in `script_timeout`. `name` is used in error
+    fn with_budget(
+        &self,
+        name: &str,
+        f: impl FnOnce() -> Result<(), DenoError>,
+    ) -> Result<(), DenoError> {
+        self.watchdog.arm(self.script_timeout);
+        let ret = f();
+        if !self.watchdog.disarm() {
+            return ret;
         }
+
+        // leave runtime usable for next scripts
+        self.js().v8_isolate().cancel_terminate_execution();
+        // script could finish right before termination
+        ret.map_err(|_| {
+            generic_error(format!(
+                "{name} is terminated after {:?}",
+                self.script_timeout
+            ))
+        })
     }
 
-    fn static_values() -> &'static mut EngineStaticValues {
-        #[allow(static_mut_refs)]
-        unsafe {
-            ENGINE_STATIC_VALUES.as_mut().unwrap()
+    fn load_module(&self, path: &Path) -> Result<(), DenoError> {
+        let specifier = deno_core::resolve_path(&path.to_string_lossy(), &env::current_dir()?)?;
+        let mut js = self.js();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let id = runtime.block_on(async {
+            let id = js.load_side_module(&specifier, None).await?;
+            let evaluated = js.mod_evaluate(id);
+            js.run_event_loop(false).await?;
+            evaluated.await?;
+
+            Ok::<_, DenoError>(id)
+        })?;
+
+        let namespace = js.get_module_namespace(id)?;
+        let scope = &mut js.handle_scope();
+        let namespace = v8::Local::new(scope, namespace);
+        let names = Self::property_names(scope, namespace);
+
+        let mut shared = self.shared.borrow_mut();
+        for name in names {
+            log::debug!("shared export {name} of {path:?}");
+            let key = v8::String::new(scope, &name).unwrap();
+            let value = namespace.get(scope, key.into()).unwrap();
+            shared.push((name, v8::Global::new(scope, value)));
         }
+
+        Ok(())
     }
 
-    pub fn cache_invalidate(&self) {
-        Self::static_values().cache.clear();
+    /// share globals added to main context by `f`
+    fn share_new_globals(
+        &self,
+        f: impl FnOnce(&mut JsRuntime) -> Result<(), DenoError>,
+    ) -> Result<(), DenoError> {
+        let mut js = self.js();
+
+        let before = Self::global_names(&mut js.handle_scope());
+        f(&mut js)?;
+
+        let scope = &mut js.handle_scope();
+        let global = scope.get_current_context().global(scope);
+        let mut shared = self.shared.borrow_mut();
+        for name in Self::global_names(scope) {
+            if before.contains(&name) {
+                continue;
+            }
+
+            log::debug!("shared global {name}");
+            let key = v8::String::new(scope, &name).unwrap();
+            let value = global.get(scope, key.into()).unwrap();
+            shared.push((name, v8::Global::new(scope, value)));
+        }
+
+        Ok(())
     }
 
-    pub fn create_computed(&self, formula: &str) -> Computed<'_> {
-        Computed {
-            formula: String::from(formula),
-            engine: self,
+    fn run_script(scope: &mut v8::HandleScope, name: &str, script: &str) -> Result<(), DenoError> {
+        let scope = &mut v8::TryCatch::new(scope);
+
+        let origin = Self::origin(scope, name, 0);
+        let script = v8::String::new(scope, script).unwrap();
+        let result =
+            v8::Script::compile(scope, script, Some(&origin)).and_then(|script| script.run(scope));
+
+        if result.is_none() {
+            let exception = scope
+                .exception()
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            return Err(JsError::from_v8_exception(scope, exception).into());
         }
+
+        Ok(())
     }
 
-    fn value(name: &str) -> Option<CachedResult<Temperature, Box<dyn Error>>> {
-        let cache = &mut Self::static_values().cache;
-        let cached = cache.get(name);
-        if let Some(&temperature) = cached {
-            return Some(CachedResult::Cached(temperature));
+    fn origin<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        name: &str,
+        line_offset: i32,
+    ) -> v8::ScriptOrigin<'s> {
+        let name = v8::String::new(scope, name).unwrap();
+        let source_map_url = v8::undefined(scope);
+        v8::ScriptOrigin::new(
+            scope,
+            name.into(),
+            line_offset,
+            0,
+            false,
+            0,
+            source_map_url.into(),
+            false,
+            false,
+            false,
+        )
+    }
+
+    fn global_names(scope: &mut v8::HandleScope) -> Vec<String> {
+        let global = scope.get_current_context().global(scope);
+        Self::property_names(scope, global)
+    }
+
+    fn property_names(scope: &mut v8::HandleScope, object: v8::Local<v8::Object>) -> Vec<String> {
+        let Some(names) = object.get_own_property_names(scope, Default::default()) else {
+            return Vec::new();
+        };
+
+        (0..names.length())
+            .filter_map(|index| {
+                let name = names.get_index(scope, index)?;
+                Some(name.to_rust_string_lossy(scope))
+            })
+            .collect()
+    }
+
+    extern "C" fn promise_reject_callback(message: v8::PromiseRejectMessage) {
+        if message.get_event() == v8::PromiseRejectEvent::PromiseRejectWithNoHandler {
+            log::warn!("unhandled promise rejection in formula");
+        }
+    }
+
+    /// state of engine owning `isolate`
+    fn state(isolate: &v8::Isolate) -> Rc<RefCell<EngineState>> {
+        isolate
+            .get_slot::<Rc<RefCell<EngineState>>>()
+            .expect("isolate is not owned by compute engine")
+            .clone()
+    }
+
+    pub fn cache_invalidate(&self) {
+        self.state.borrow_mut().cache.clear();
+    }
+
+    /// forget cached values, history and state of helpers, so next formulas are computed
+    /// like in new engine. globals of shared scripts are kept
+    pub fn reset(&self) {
+        let mut state = self.state.borrow_mut();
+        state.cache.clear();
+        state.history.clear();
+        state.helpers = HelpersState::default();
+    }
+
+    /// compile formula of fan with index `fan` into function which must finish in `timeout`.
+    /// `name` is used in logs and errors
+    pub fn create_computed(
+        &self,
+        fan: usize,
+        name: &str,
+        formula: &str,
+        timeout: Duration,
+    ) -> Result<Computed<'_>, ComputedError> {
+        let mut js = self.js();
+        let scope = &mut js.handle_scope();
+
+        // every formula has own context to avoid collisions of globals
+        let main = scope.get_current_context();
+        let token = main.get_security_token(scope);
+        let context = v8::Context::new(scope);
+        context.set_security_token(token);
+        let scope = &mut v8::ContextScope::new(scope, context);
+
+        let global = context.global(scope);
+        Self::install_globals(scope, global);
+        for (name, value) in self.shared.borrow().iter() {
+            let name = v8::String::new(scope, name).unwrap();
+            let value = v8::Local::new(scope, value);
+            global.set(scope, name.into(), value);
         }
 
-        let source = Self::static_values().sources.get(name);
-        if let Some(source) = source {
-            let result = source.try_get_temperature();
-            match result {
-                Ok(temperature) => {
-                    cache.insert(name.to_string(), temperature);
-                    Some(CachedResult::Some(temperature))
+        // formula with single expression is used as result
+        let expression = formula.trim_end().trim_end_matches(';');
+        let function = match Self::compile(scope, name, &format!("return (\n{expression}\n);"), -1)
+        {
+            Ok(function) => function,
+            Err(_) => {
+                let function = Self::compile(scope, name, formula, 0)?;
+                // otherwise result is always `undefined`
+                if !Self::has_return(scope, formula) {
+                    return Err(ComputedError::NoReturn {
+                        fan: name.to_string(),
+                    });
                 }
-                Err(err) => Some(CachedResult::Err(err)),
+                function
             }
-        } else {
-            None
+        };
+
+        let sources = v8::Object::new(scope);
+        Self::install_sources(scope, sources);
+
+        let state = v8::Object::new(scope);
+
+        let history = v8::Object::new(scope);
+        for key in self.source_names() {
+            let name = v8::String::new(scope, &key).unwrap();
+            history.set_accessor(scope, name.into(), Self::history_accessor);
         }
+        history.set_integrity_level(scope, v8::IntegrityLevel::Frozen);
+
+        self.state
+            .borrow_mut()
+            .consoles
+            .insert(fan, Console::new(name));
+
+        Ok(Computed {
+            fan,
+            name: name.to_string(),
+            timeout,
+            context: v8::Global::new(scope, context),
+            function: v8::Global::new(scope, function),
+            sources: v8::Global::new(scope, sources),
+            state: v8::Global::new(scope, state),
+            history: v8::Global::new(scope, history),
+            engine: self,
+            hold: MinHold::default(),
+            previous: None,
+            last_tick: None,
+            reason: None,
+        })
+    }
+
+    /// whether `body` has `return` outside of nested functions. such body is not valid script
+    fn has_return(scope: &mut v8::HandleScope, body: &str) -> bool {
+        let scope = &mut v8::TryCatch::new(scope);
+        let source = v8::String::new(scope, body).unwrap();
+        if v8::Script::compile(scope, source, None).is_some() {
+            return false;
+        }
+
+        // other errors are possible only in function body (e.g. `new.target`), body is kept
+        scope.message().is_none_or(|message| {
+            message
+                .get(scope)
+                .to_rust_string_lossy(scope)
+                .contains("Illegal return")
+        })
+    }
+
+    fn compile<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        fan: &str,
+        body: &str,
+        line_offset: i32,
+    ) -> Result<v8::Local<'s, v8::Function>, ComputedError> {
+        let scope = &mut v8::TryCatch::new(scope);
+
+        let origin = Self::origin(scope, fan, line_offset);
+        let body = v8::String::new(scope, body).unwrap();
+        let source = v8::script_compiler::Source::new(body, Some(&origin));
+
+        let arguments: Vec<_> = FORMULA_ARGUMENTS
+            .iter()
+            .map(|arg| v8::String::new(scope, arg).unwrap())
+            .collect();
+
+        let function = v8::script_compiler::compile_function(
+            scope,
+            source,
+            &arguments,
+            &[],
+            v8::script_compiler::CompileOptions::NoCompileOptions,
+            v8::script_compiler::NoCacheReason::NoReason,
+        );
+
+        function.ok_or_else(|| {
+            let (message, line, column) = match scope.message() {
+                Some(message) => (
+                    message.get(scope).to_rust_string_lossy(scope),
+                    message.get_line_number(scope).unwrap_or(0),
+                    message.get_start_column() + 1,
+                ),
+                None => ("unknown error".to_string(), 0, 0),
+            };
+
+            ComputedError::Compile {
+                fan: fan.to_string(),
+                line,
+                column,
+                message,
+            }
+        })
     }
 
-    fn middleware<'s>(scope: &mut v8::HandleScope<'s>, value: v8::Local<'s, v8::Object>) {
-        for (key, _) in &Self::static_values().sources {
-            let name = v8::String::new(scope, key).unwrap();
-            value.set_accessor(scope, name.into(), Self::accessor);
+    fn source_names(&self) -> Vec<String> {
+        self.state.borrow().sources.keys().cloned().collect()
+    }
+
+    /// add sources, `fand` and `console` to `global`
+    fn install_globals<'s>(scope: &mut v8::HandleScope<'s>, global: v8::Local<'s, v8::Object>) {
+        Self::install_sources(scope, global);
+        Self::install_stdlib(scope, global);
+        Self::install_console(scope, global);
+    }
+
+    /// replace `console` of `global` with one writing to log
+    fn install_console<'s>(scope: &mut v8::HandleScope<'s>, global: v8::Local<'s, v8::Object>) {
+        let name = v8::String::new(scope, "console").unwrap();
+        let console = console::create(scope);
+        global.set(scope, name.into(), console.into());
+    }
+
+    /// add `fand` object with helpers to `global`
+    fn install_stdlib<'s>(scope: &mut v8::HandleScope<'s>, global: v8::Local<'s, v8::Object>) {
+        let name = v8::String::new(scope, "fand").unwrap();
+        let fand = stdlib::create(scope);
+        global.set(scope, name.into(), fand.into());
+    }
+
+    /// add property for every source to `object`
+    fn install_sources<'s>(scope: &mut v8::HandleScope<'s>, object: v8::Local<'s, v8::Object>) {
+        let names: Vec<_> = Self::state(scope)
+            .borrow()
+            .sources
+            .keys()
+            .cloned()
+            .collect();
+        for key in names {
+            let name = v8::String::new(scope, &key).unwrap();
+            object.set_accessor(scope, name.into(), Self::accessor);
         }
     }
 
@@ -111,7 +566,7 @@ impl ComputeEngine {
     ) {
         let name = name.to_rust_string_lossy(scope);
         log::trace!("accessing {name}");
-        let value = Self::value(&name);
+        let value = Self::state(scope).borrow_mut().value(&name);
         if let Some(value) = value {
             match value {
                 CachedResult::Cached(temperature) => {
@@ -134,32 +589,583 @@ impl ComputeEngine {
             }
         }
     }
+
+    /// returns frozen array with history of source including current value
+    fn history_accessor<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        name: v8::Local<'s, v8::Name>,
+        _: v8::PropertyCallbackArguments<'s>,
+        mut ret: v8::ReturnValue,
+    ) {
+        let name = name.to_rust_string_lossy(scope);
+        let state = Self::state(scope);
+        let mut state = state.borrow_mut();
+        if let Some(CachedResult::Err(err)) = state.value(&name) {
+            log::debug!("history of {name} has no current value: {err:?}");
+        }
+
+        let samples: Vec<_> = state
+            .history
+            .get(&name)
+            .into_iter()
+            .flatten()
+            .map(|&sample| v8::Number::new(scope, sample).into())
+            .collect();
+        let array = v8::Array::new_with_elements(scope, &samples);
+        array.set_integrity_level(scope, v8::IntegrityLevel::Frozen);
+        ret.set(array.into());
+    }
 }
 
-impl<'a> Computed<'a> {
-    pub fn try_compute(&self) -> Result<FanPower, DenoError> {
-        let mut js = self.engine.js.borrow_mut();
-        let result = js.execute_script(
-            "[computed.rs:runtime.js]",
-            FastString::Owned(Box::from(self.formula.as_str())),
-        )?;
-
-        let mut scope = js.handle_scope();
-        let result = result.into_raw();
-        let result = unsafe { result.as_ref() };
-        let power = if !result.is_number() {
-            log::warn!("computed value {result:?} is not a number. Set full speed");
-            FanPower::full_speed()
+impl Deref for EnteredRuntime<'_> {
+    type Target = JsRuntime;
+
+    fn deref(&self) -> &JsRuntime {
+        &self.0
+    }
+}
+
+impl DerefMut for EnteredRuntime<'_> {
+    fn deref_mut(&mut self) -> &mut JsRuntime {
+        &mut self.0
+    }
+}
+
+impl Drop for EnteredRuntime<'_> {
+    fn drop(&mut self) {
+        unsafe { self.0.v8_isolate().exit() };
+    }
+}
+
+impl EngineState {
+    fn value(&mut self, name: &str) -> Option<CachedResult<Temperature, Box<dyn Error>>> {
+        if let Some(&temperature) = self.cache.get(name) {
+            return Some(CachedResult::Cached(temperature));
+        }
+
+        let source = self.sources.get(name)?;
+        match source.try_get_temperature() {
+            Ok(temperature) => {
+                self.cache.insert(name.to_string(), temperature);
+                self.record(name, temperature);
+                Some(CachedResult::Some(temperature))
+            }
+            Err(err) => Some(CachedResult::Err(err)),
+        }
+    }
+
+    /// add sample to history of source
+    fn record(&mut self, name: &str, temperature: Temperature) {
+        let history = self.history.entry(name.to_string()).or_default();
+
+        history.push_back(temperature.celsius() as f64);
+        while history.len() > self.history_len {
+            history.pop_front();
+        }
+    }
+}
+
+impl FormulaResult {
+    /// returns `None` if exception is thrown
+    fn parse<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+    ) -> Option<Result<Self, String>> {
+        if value.is_number() {
+            let power = value.number_value(scope)?;
+            return Some(Self::power(power).map(|power| Self {
+                power,
+                reason: None,
+                min_hold: Duration::ZERO,
+            }));
+        }
+
+        let Ok(object) = v8::Local::<v8::Object>::try_from(value) else {
+            let kind = value.type_of(scope).to_rust_string_lossy(scope);
+            return Some(Err(format!(
+                "returned {kind}, expected number or object with power"
+            )));
+        };
+
+        let power = Self::property(scope, object, "power")?;
+        if !power.is_number() {
+            return Some(Err("returned object without numeric power".to_string()));
+        }
+        let power = match Self::power(power.number_value(scope)?) {
+            Ok(power) => power,
+            Err(err) => return Some(Err(err)),
+        };
+
+        let reason = Self::property(scope, object, "reason")?;
+        let reason = if reason.is_null_or_undefined() {
+            None
+        } else if reason.is_string() {
+            Some(reason.to_rust_string_lossy(scope))
         } else {
-            let power = unsafe { result.to_number(&mut scope).unwrap_unchecked() };
-            let power = power.value();
-            let power = f64::min(f64::max(0.0, power), 1.0);
-            let power = FanPower::from((power * 255.0) as u8);
-            log::debug!("computed power: {power:7.2}");
+            return Some(Err("reason must be a string".to_string()));
+        };
+
+        let min_hold = Self::property(scope, object, "min_hold")?;
+        let min_hold = if min_hold.is_null_or_undefined() {
+            Duration::ZERO
+        } else {
+            let secs = min_hold
+                .is_number()
+                .then(|| min_hold.number_value(scope))
+                .flatten()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+            match secs {
+                Some(secs) => secs,
+                None => {
+                    return Some(Err(
+                        "min_hold must be a non-negative number of seconds".to_string()
+                    ))
+                }
+            }
+        };
+
+        Some(Ok(Self {
+            power,
+            reason,
+            min_hold,
+        }))
+    }
+
+    fn property<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        object: v8::Local<'s, v8::Object>,
+        name: &str,
+    ) -> Option<v8::Local<'s, v8::Value>> {
+        let key = v8::String::new(scope, name).unwrap();
+        object.get(scope, key.into())
+    }
+
+    fn power(power: f64) -> Result<f64, String> {
+        if power.is_nan() {
+            return Err("returned NaN".to_string());
+        }
+        Ok(power.clamp(0.0, 1.0))
+    }
+}
+
+impl MinHold {
+    /// returns power and reason to apply
+    fn apply(&mut self, result: FormulaResult, now: Instant) -> (f64, Option<String>) {
+        if let Some(hold) = &self.hold {
+            if now >= hold.until {
+                self.hold = None;
+            } else if result.power < hold.power {
+                return (hold.power, hold.reason.clone());
+            }
+        }
+
+        if !result.min_hold.is_zero() {
+            self.hold = Some(Hold {
+                power: result.power,
+                until: now + result.min_hold,
+                reason: result.reason.clone(),
+            });
+        }
+
+        (result.power, result.reason)
+    }
+
+    /// power kept by active hold
+    fn held(&self, now: Instant) -> Option<f64> {
+        self.hold
+            .as_ref()
+            .filter(|hold| now < hold.until)
+            .map(|hold| hold.power)
+    }
+}
+
+impl<'a> Computed<'a> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// reason given by formula for last result
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// remember power applied to fan, it's available to formula on next tick
+    pub fn applied(&mut self, power: FanPower) {
+        self.previous = Some(power.fraction());
+    }
+
+    /// create frozen `context` argument of formula
+    fn tick_context<'s>(&mut self, scope: &mut v8::HandleScope<'s>) -> v8::Local<'s, v8::Object> {
+        let now = Instant::now();
+        let elapsed = self
+            .last_tick
+            .replace(now)
+            .map(|last| now.duration_since(last).as_secs_f64());
+        let uptime = now.duration_since(self.engine.started).as_secs_f64();
 
-            power
+        let number = |scope: &mut v8::HandleScope<'s>, value: Option<f64>| match value {
+            Some(value) => v8::Number::new(scope, value).into(),
+            None => v8::null(scope).into(),
         };
+        let properties = [
+            ("previous", number(scope, self.previous)),
+            ("elapsed", number(scope, elapsed)),
+            ("hour", number(scope, Some(local_hour() as f64))),
+            ("uptime", number(scope, Some(uptime))),
+        ];
+
+        let context = v8::Object::new(scope);
+        for (name, value) in properties {
+            let name = v8::String::new(scope, name).unwrap();
+            context.set(scope, name.into(), value);
+        }
+        context.set_integrity_level(scope, v8::IntegrityLevel::Frozen);
+
+        context
+    }
+
+    /// reason is cleared on error
+    pub fn try_compute(&mut self) -> Result<FanPower, DenoError> {
+        let ret = self.compute();
+        if ret.is_err() {
+            self.reason = None;
+        }
+        ret
+    }
+
+    /// power applied instead of `failsafe` when formula fails. active `min_hold` is not undercut
+    pub fn failsafe(&self, failsafe: FanPower) -> FanPower {
+        match self.hold.held(Instant::now()) {
+            Some(power) if power > failsafe.fraction() => FanPower::from((power * 255.0) as u8),
+            _ => failsafe,
+        }
+    }
+
+    fn compute(&mut self) -> Result<FanPower, DenoError> {
+        let mut js = self.engine.js();
+        let scope = &mut js.handle_scope();
+        self.engine.state.borrow_mut().current_fan = self.fan;
+
+        let result = {
+            let context = v8::Local::new(scope, &self.context);
+            let scope = &mut v8::ContextScope::new(scope, context);
+            let scope = &mut v8::TryCatch::new(scope);
+
+            let function = v8::Local::new(scope, &self.function);
+            let sources = v8::Local::new(scope, &self.sources);
+            let state = v8::Local::new(scope, &self.state);
+            let history = v8::Local::new(scope, &self.history);
+            let tick = self.tick_context(scope);
+            let recv = v8::undefined(scope);
+
+            self.engine.watchdog.arm(self.timeout);
+            let args = [sources.into(), state.into(), tick.into(), history.into()];
+            let result = function
+                .call(scope, recv.into(), &args)
+                .and_then(|value| FormulaResult::parse(scope, value));
+
+            result.ok_or_else(|| {
+                scope
+                    .exception()
+                    .unwrap_or_else(|| v8::undefined(scope).into())
+            })
+        };
+
+        if self.engine.watchdog.disarm() {
+            // leave runtime usable for next computations
+            scope.cancel_terminate_execution();
+            // formula could finish right before termination
+            if result.is_err() {
+                return Err(generic_error(format!(
+                    "formula of {} is terminated after {:?}",
+                    self.name, self.timeout
+                )));
+            }
+        }
+
+        // errors are converted in main context which is known by deno_core
+        let result = result.map_err(|exception| JsError::from_v8_exception(scope, exception))?;
+        let result =
+            result.map_err(|err| generic_error(format!("formula of {} {err}", self.name)))?;
+
+        let (power, reason) = self.hold.apply(result, Instant::now());
+        if reason.is_some() && reason != self.reason {
+            log::info!("{}: {}", self.name, reason.as_deref().unwrap_or_default());
+        }
+        self.reason = reason;
+
+        let power = FanPower::from((power * 255.0) as u8);
+        log::debug!("computed power: {power:7.2}");
 
         Ok(power)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ComputeEngine, FormulaResult, MinHold};
+    use crate::{
+        fan::FanPower,
+        source::{Source, Temperature},
+    };
+    use std::{
+        cell::Cell,
+        collections::HashMap,
+        error::Error,
+        rc::Rc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    /// source with value set by test. `None` is an error
+    struct MockSource(Cell<Option<f32>>);
+
+    impl Source for MockSource {
+        fn try_get_temperature(&self) -> Result<Temperature, Box<dyn Error>> {
+            let value = self.0.get().ok_or("mock source error")?;
+            Ok(Temperature::from_celsius(value))
+        }
+    }
+
+    fn engine(sources: &[(&str, &Rc<MockSource>)]) -> ComputeEngine {
+        let sources = sources
+            .iter()
+            .map(|&(name, source)| (name.to_string(), source.clone() as Rc<dyn Source>))
+            .collect::<HashMap<_, _>>();
+        ComputeEngine::new(sources, 3)
+    }
+
+    fn mock(value: f32) -> Rc<MockSource> {
+        Rc::new(MockSource(Cell::new(Some(value))))
+    }
+
+    fn compute(engine: &ComputeEngine, formula: &str) -> Result<f64, String> {
+        let mut computed = engine
+            .create_computed(0, "fan", formula, TIMEOUT)
+            .map_err(|err| err.to_string())?;
+        computed
+            .try_compute()
+            .map(|power| power.fraction())
+            .map_err(|err| err.to_string())
+    }
+
+    fn result(power: f64, reason: Option<&str>, min_hold: u64) -> FormulaResult {
+        FormulaResult {
+            power,
+            reason: reason.map(str::to_string),
+            min_hold: Duration::from_secs(min_hold),
+        }
+    }
+
+    #[test]
+    fn min_hold() {
+        let mut hold = MinHold::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(hold.apply(result(0.25, None, 0), at(0)), (0.25, None));
+        assert_eq!(
+            hold.apply(result(0.75, Some("hot"), 10), at(1)),
+            (0.75, Some("hot".to_string()))
+        );
+
+        // power does not drop while hold is active
+        assert_eq!(
+            hold.apply(result(0.5, None, 0), at(5)),
+            (0.75, Some("hot".to_string()))
+        );
+        assert_eq!(hold.apply(result(1.0, None, 0), at(6)), (1.0, None));
+        assert_eq!(
+            hold.apply(result(0.5, Some("cool"), 10), at(10)),
+            (0.75, Some("hot".to_string()))
+        );
+
+        assert_eq!(hold.held(at(10)), Some(0.75));
+
+        // hold is expired
+        assert_eq!(hold.held(at(11)), None);
+        assert_eq!(hold.apply(result(0.5, None, 0), at(11)), (0.5, None));
+        assert_eq!(hold.apply(result(0.25, None, 0), at(12)), (0.25, None));
+    }
+
+    #[test]
+    fn sources() {
+        let cpu = mock(51.0);
+        let gpu = mock(102.0);
+        let engine = engine(&[("cpu", &cpu), ("gpu", &gpu)]);
+
+        assert_eq!(compute(&engine, "cpu / 102"), Ok(127.0 / 255.0));
+        assert_eq!(compute(&engine, "sources.gpu / 102"), Ok(1.0));
+        assert_eq!(compute(&engine, "gpu / 51"), Ok(1.0));
+        assert_eq!(compute(&engine, "-cpu"), Ok(0.0));
+
+        // values are cached until invalidation
+        cpu.0.set(Some(0.0));
+        assert_eq!(compute(&engine, "cpu / 102"), Ok(127.0 / 255.0));
+        engine.cache_invalidate();
+        assert_eq!(compute(&engine, "cpu / 102"), Ok(0.0));
+
+        cpu.0.set(None);
+        engine.cache_invalidate();
+        assert!(compute(&engine, "cpu / 102").is_err());
+    }
+
+    #[test]
+    fn results() {
+        let cpu = mock(51.0);
+        let engine = engine(&[("cpu", &cpu)]);
+
+        assert_eq!(compute(&engine, "({ power: 1, reason: 'hot' })"), Ok(1.0));
+        assert!(compute(&engine, "'fast'").is_err());
+        assert!(compute(&engine, "NaN").is_err());
+        assert!(compute(&engine, "({ reason: 'hot' })").is_err());
+        assert!(compute(&engine, "({ power: 1, min_hold: -1 })").is_err());
+        assert!(compute(&engine, "cpu +").is_err());
+        assert!(compute(&engine, "while (true) {}").is_err());
+
+        // engine is usable after termination
+        assert_eq!(compute(&engine, "cpu / 51"), Ok(1.0));
+    }
+
+    #[test]
+    fn failsafe() {
+        let cpu = mock(51.0);
+        let engine = engine(&[("cpu", &cpu)]);
+        let formula = "({ power: cpu / 102, reason: 'warm', min_hold: 60 })";
+        let mut computed = engine.create_computed(0, "fan", formula, TIMEOUT).unwrap();
+
+        let power = computed.try_compute().unwrap().fraction();
+        assert_eq!(power, 127.0 / 255.0);
+        assert_eq!(computed.reason(), Some("warm"));
+
+        cpu.0.set(None);
+        engine.cache_invalidate();
+        assert!(computed.try_compute().is_err());
+        assert_eq!(computed.reason(), None);
+
+        // failsafe lower than held power does not undercut hold
+        assert_eq!(computed.failsafe(FanPower::from(51)).fraction(), power);
+        assert_eq!(computed.failsafe(FanPower::from(255)).fraction(), 1.0);
+    }
+
+    #[test]
+    fn statements() {
+        let cpu = mock(51.0);
+        let engine = engine(&[("cpu", &cpu)]);
+
+        assert_eq!(compute(&engine, "cpu / 51;"), Ok(1.0));
+        assert_eq!(compute(&engine, "cpu / 51 ;\n"), Ok(1.0));
+        assert_eq!(compute(&engine, "const x = cpu / 51;\nreturn x;"), Ok(1.0));
+        assert_eq!(
+            compute(&engine, "const f = () => { return cpu / 51 };\nreturn f();"),
+            Ok(1.0)
+        );
+        assert_eq!(
+            compute(&engine, "if (cpu > 50) { return 1 }\nreturn 0"),
+            Ok(1.0)
+        );
+
+        // result of last statement is not used
+        let no_return = Err(
+            "formula of fan has several statements but no top-level `return`, \
+             add `return` before its result"
+                .to_string(),
+        );
+        for formula in [
+            "const x = cpu / 51;\nx",
+            "const returned = cpu / 51; returned;",
+            "function f() { return cpu / 51 }\nf()",
+            "const f = () => { return cpu / 51 };\nf()",
+            "const x = 'return';\nx",
+            "// return\nconst x = cpu / 51;\nx",
+        ] {
+            assert_eq!(compute(&engine, formula), no_return, "{formula}");
+        }
+    }
+
+    #[test]
+    fn scripts_budget() {
+        let cpu = mock(51.0);
+        let mut engine = engine(&[("cpu", &cpu)]);
+        engine.script_timeout = TIMEOUT;
+
+        assert_eq!(
+            engine
+                .load_shared("while (true) {}")
+                .map_err(|err| err.to_string()),
+            Err("shared script is terminated after 50ms".to_string())
+        );
+
+        // engine is usable after termination
+        engine.load_shared("var scale = 51;").unwrap();
+        assert_eq!(compute(&engine, "cpu / scale"), Ok(1.0));
+    }
+
+    #[test]
+    fn history() {
+        let cpu = mock(10.0);
+        let engine = engine(&[("cpu", &cpu)]);
+        let formula = "({ power: 0, reason: history.cpu.join(' ') })";
+        let mut computed = engine.create_computed(0, "fan", formula, TIMEOUT).unwrap();
+
+        let mut reasons = Vec::new();
+        for value in [10.0, 20.0, 30.0, 40.0] {
+            cpu.0.set(Some(value));
+            computed.try_compute().unwrap();
+            reasons.push(computed.reason().unwrap().to_string());
+            engine.cache_invalidate();
+        }
+
+        // history keeps last 3 samples
+        assert_eq!(reasons, ["10", "10 20", "10 20 30", "20 30 40"]);
+    }
+
+    #[test]
+    fn multiple_engines() {
+        let cpu = mock(51.0);
+        let gpu = mock(102.0);
+        let engine_cpu = engine(&[("cpu", &cpu)]);
+        let engine_gpu = engine(&[("cpu", &gpu), ("gpu", &gpu)]);
+        let mut computed_cpu = engine_cpu
+            .create_computed(0, "fan", "cpu / 102", TIMEOUT)
+            .unwrap();
+        let mut computed_gpu = engine_gpu
+            .create_computed(0, "fan", "cpu / 102", TIMEOUT)
+            .unwrap();
+
+        // every engine reads own sources while both are alive
+        for _ in 0..3 {
+            let power = computed_cpu.try_compute().unwrap().fraction();
+            assert_eq!(power, 127.0 / 255.0);
+            let power = computed_gpu.try_compute().unwrap().fraction();
+            assert_eq!(power, 1.0);
+        }
+        assert!(compute(&engine_cpu, "gpu").is_err());
+        assert_eq!(compute(&engine_gpu, "gpu / 102"), Ok(1.0));
+        assert!(compute(&engine_cpu, "while (true) {}").is_err());
+        assert_eq!(computed_gpu.try_compute().unwrap().fraction(), 1.0);
+        assert_eq!(
+            computed_cpu.try_compute().unwrap().fraction(),
+            127.0 / 255.0
+        );
+        drop((computed_cpu, computed_gpu));
+        drop(engine_gpu);
+        drop(engine_cpu);
+
+        let threads: Vec<_> = (0..4)
+            .map(|index| {
+                thread::spawn(move || {
+                    let cpu = mock(index as f32);
+                    let engine = engine(&[("cpu", &cpu)]);
+                    compute(&engine, "cpu / 4")
+                })
+            })
+            .collect();
+        for (index, thread) in threads.into_iter().enumerate() {
+            let expected = (index as f64 / 4.0 * 255.0) as u8 as f64 / 255.0;
+            assert_eq!(thread.join().unwrap(), Ok(expected));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::{stdlib::set_function, ComputeEngine};
+use deno_core::v8;
+use log::Level;
+use std::time::{Duration, Instant};
+
+/// log target of messages from formulas
+const TARGET: &str = "fand::formula";
+
+/// at most `MAX_MESSAGES` messages per `WINDOW` are logged for every fan
+const WINDOW: Duration = Duration::from_secs(60);
+const MAX_MESSAGES: u32 = 10;
+
+/// `console` of fan formula
+pub struct Console {
+    name: String,
+    window: Instant,
+    count: u32,
+    suppressed: u32,
+}
+
+impl Console {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            window: Instant::now(),
+            count: 0,
+            suppressed: 0,
+        }
+    }
+
+    /// returns `None` if message must be suppressed, otherwise count of messages suppressed before
+    fn allow(&mut self, now: Instant) -> Option<u32> {
+        if now.duration_since(self.window) >= WINDOW {
+            self.window = now;
+            self.count = 0;
+        }
+
+        if self.count >= MAX_MESSAGES {
+            self.suppressed += 1;
+            return None;
+        }
+
+        self.count += 1;
+        Some(std::mem::take(&mut self.suppressed))
+    }
+
+    fn log(&mut self, level: Level, message: &str) {
+        let Some(suppressed) = self.allow(Instant::now()) else {
+            return;
+        };
+
+        let name = &self.name;
+        if suppressed > 0 {
+            log::warn!(target: TARGET, "{name}: {suppressed} messages suppressed");
+        }
+        log::log!(target: TARGET, level, "{name}: {message}");
+    }
+}
+
+/// create `console` object which forwards messages to log
+pub fn create<'s>(scope: &mut v8::HandleScope<'s>) -> v8::Local<'s, v8::Object> {
+    let console = v8::Object::new(scope);
+
+    set_function(scope, console, "log", log_callback);
+    set_function(scope, console, "info", info_callback);
+    set_function(scope, console, "warn", warn_callback);
+    set_function(scope, console, "error", error_callback);
+    set_function(scope, console, "debug", debug_callback);
+
+    console
+}
+
+fn message(scope: &mut v8::HandleScope, args: &v8::FunctionCallbackArguments) -> String {
+    let mut message = String::new();
+
+    for index in 0..args.length() {
+        let value = args.get(index);
+        let value = if value.is_object() && !value.is_function() {
+            v8::json::stringify(scope, value)
+                .map(|json| json.to_rust_string_lossy(scope))
+                .unwrap_or_else(|| value.to_rust_string_lossy(scope))
+        } else {
+            value.to_rust_string_lossy(scope)
+        };
+
+        if index > 0 {
+            message.push(' ');
+        }
+        message.push_str(&value);
+    }
+
+    message
+}
+
+fn console(scope: &mut v8::HandleScope, args: &v8::FunctionCallbackArguments, level: Level) {
+    if !log::log_enabled!(target: TARGET, level) {
+        return;
+    }
+
+    let message = message(scope, args);
+    let state = ComputeEngine::state(scope);
+    let mut state = state.borrow_mut();
+    let fan = state.current_fan;
+    match state.consoles.get_mut(&fan) {
+        Some(console) => console.log(level, &message),
+        None => log::log!(target: TARGET, level, "{message}"),
+    }
+}
+
+fn log_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    console(scope, &args, Level::Info);
+}
+
+fn info_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    console(scope, &args, Level::Info);
+}
+
+fn warn_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    console(scope, &args, Level::Warn);
+}
+
+fn error_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    console(scope, &args, Level::Error);
+}
+
+fn debug_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    console(scope, &args, Level::Debug);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Console, MAX_MESSAGES, WINDOW};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn rate_limit() {
+        let mut console = Console::new("fan[0]");
+        let start = Instant::now();
+
+        for _ in 0..MAX_MESSAGES {
+            assert_eq!(console.allow(start), Some(0));
+        }
+        assert_eq!(console.allow(start), None);
+        assert_eq!(console.allow(start + Duration::from_secs(1)), None);
+
+        let next = start + WINDOW;
+        assert_eq!(console.allow(next), Some(2));
+        assert_eq!(console.allow(next), Some(0));
+    }
+}
@@ -0,0 +1,358 @@
+use super::ComputeEngine;
+use deno_core::v8;
+use std::{collections::HashMap, time::Instant};
+
+/// state of stateful helpers. keyed by fan and id given by formula
+#[derive(Default)]
+pub struct HelpersState {
+    hysteresis: HashMap<(usize, String), f64>,
+    ema: HashMap<(usize, String), f64>,
+    rate_limit: HashMap<(usize, String), (f64, Instant)>,
+}
+
+pub fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+pub fn clamp(value: f64, min: f64, max: f64) -> f64 {
+    value.max(min).min(max)
+}
+
+/// linear interpolation between `(temp, power)` points sorted by temp
+pub fn curve(temp: f64, points: &[(f64, f64)]) -> Option<f64> {
+    let &(first_temp, first_power) = points.first()?;
+    if temp <= first_temp {
+        return Some(first_power);
+    }
+
+    for window in points.windows(2) {
+        let [(temp0, power0), (temp1, power1)] = [window[0], window[1]];
+        if temp <= temp1 {
+            if temp1 == temp0 {
+                return Some(power1);
+            }
+            return Some(lerp(power0, power1, (temp - temp0) / (temp1 - temp0)));
+        }
+    }
+
+    points.last().map(|&(_, power)| power)
+}
+
+impl HelpersState {
+    /// keep previous result while `value` is within `band` of it
+    pub fn hysteresis(&mut self, fan: usize, id: &str, value: f64, band: f64) -> f64 {
+        let last = self
+            .hysteresis
+            .entry((fan, id.to_string()))
+            .or_insert(value);
+
+        if (value - *last).abs() > band {
+            *last = value;
+        }
+        *last
+    }
+
+    /// exponential moving average
+    pub fn ema(&mut self, fan: usize, id: &str, value: f64, alpha: f64) -> f64 {
+        let average = self.ema.entry((fan, id.to_string())).or_insert(value);
+
+        *average += clamp(alpha, 0.0, 1.0) * (value - *average);
+        *average
+    }
+
+    /// move towards `value` not faster than `per_second`
+    pub fn rate_limit(
+        &mut self,
+        fan: usize,
+        id: &str,
+        value: f64,
+        per_second: f64,
+        now: Instant,
+    ) -> f64 {
+        let key = (fan, id.to_string());
+        let Some((last, time)) = self.rate_limit.get_mut(&key) else {
+            self.rate_limit.insert(key, (value, now));
+            return value;
+        };
+
+        let max = per_second.abs() * now.duration_since(*time).as_secs_f64();
+        *last += clamp(value - *last, -max, max);
+        *time = now;
+        *last
+    }
+}
+
+/// create `fand` object with helpers for formulas
+pub fn create<'s>(scope: &mut v8::HandleScope<'s>) -> v8::Local<'s, v8::Object> {
+    let fand = v8::Object::new(scope);
+
+    set_function(scope, fand, "lerp", lerp_callback);
+    set_function(scope, fand, "clamp", clamp_callback);
+    set_function(scope, fand, "curve", curve_callback);
+    set_function(scope, fand, "hysteresis", hysteresis_callback);
+    set_function(scope, fand, "ema", ema_callback);
+    set_function(scope, fand, "rateLimit", rate_limit_callback);
+
+    fand
+}
+
+pub fn set_function<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    object: v8::Local<'s, v8::Object>,
+    name: &str,
+    callback: impl v8::MapFnTo<v8::FunctionCallback>,
+) {
+    let function = v8::Function::new(scope, callback).unwrap();
+    let name = v8::String::new(scope, name).unwrap();
+    object.set(scope, name.into(), function.into());
+}
+
+fn throw_type_error(scope: &mut v8::HandleScope, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::type_error(scope, message);
+    scope.throw_exception(exception);
+}
+
+fn number(
+    scope: &mut v8::HandleScope,
+    args: &v8::FunctionCallbackArguments,
+    index: i32,
+    name: &str,
+) -> Option<f64> {
+    let value = args.get(index);
+    if !value.is_number() {
+        throw_type_error(scope, &format!("{name} must be a number"));
+        return None;
+    }
+
+    value.number_value(scope)
+}
+
+fn id(
+    scope: &mut v8::HandleScope,
+    args: &v8::FunctionCallbackArguments,
+    index: i32,
+) -> Option<String> {
+    let value = args.get(index);
+    if !value.is_string() && !value.is_number() {
+        throw_type_error(scope, "id must be a string or a number");
+        return None;
+    }
+
+    Some(value.to_rust_string_lossy(scope))
+}
+
+fn points(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Option<Vec<(f64, f64)>> {
+    let array = v8::Local::<v8::Array>::try_from(value).ok()?;
+
+    let mut points = Vec::with_capacity(array.length() as usize);
+    for index in 0..array.length() {
+        let point = array.get_index(scope, index)?;
+        let point = v8::Local::<v8::Array>::try_from(point).ok()?;
+        let temp = point.get_index(scope, 0)?;
+        let power = point.get_index(scope, 1)?;
+        if point.length() != 2 || !temp.is_number() || !power.is_number() {
+            return None;
+        }
+
+        points.push((temp.number_value(scope)?, power.number_value(scope)?));
+    }
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    Some(points)
+}
+
+fn lerp_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut ret: v8::ReturnValue,
+) {
+    let Some(a) = number(scope, &args, 0, "a") else {
+        return;
+    };
+    let Some(b) = number(scope, &args, 1, "b") else {
+        return;
+    };
+    let Some(t) = number(scope, &args, 2, "t") else {
+        return;
+    };
+
+    ret.set_double(lerp(a, b, t));
+}
+
+fn clamp_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut ret: v8::ReturnValue,
+) {
+    let Some(value) = number(scope, &args, 0, "value") else {
+        return;
+    };
+    let Some(min) = number(scope, &args, 1, "min") else {
+        return;
+    };
+    let Some(max) = number(scope, &args, 2, "max") else {
+        return;
+    };
+
+    ret.set_double(clamp(value, min, max));
+}
+
+fn curve_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut ret: v8::ReturnValue,
+) {
+    let Some(temp) = number(scope, &args, 0, "temp") else {
+        return;
+    };
+    let points = points(scope, args.get(1)).unwrap_or_default();
+
+    match curve(temp, &points) {
+        Some(power) => ret.set_double(power),
+        None => throw_type_error(scope, "points must be a non-empty array of [temp, power]"),
+    }
+}
+
+fn hysteresis_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut ret: v8::ReturnValue,
+) {
+    let Some(id) = id(scope, &args, 0) else {
+        return;
+    };
+    let Some(value) = number(scope, &args, 1, "value") else {
+        return;
+    };
+    let Some(band) = number(scope, &args, 2, "band") else {
+        return;
+    };
+
+    let state = ComputeEngine::state(scope);
+    let mut state = state.borrow_mut();
+    let fan = state.current_fan;
+    ret.set_double(state.helpers.hysteresis(fan, &id, value, band));
+}
+
+fn ema_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut ret: v8::ReturnValue,
+) {
+    let Some(id) = id(scope, &args, 0) else {
+        return;
+    };
+    let Some(value) = number(scope, &args, 1, "value") else {
+        return;
+    };
+    let Some(alpha) = number(scope, &args, 2, "alpha") else {
+        return;
+    };
+
+    let state = ComputeEngine::state(scope);
+    let mut state = state.borrow_mut();
+    let fan = state.current_fan;
+    ret.set_double(state.helpers.ema(fan, &id, value, alpha));
+}
+
+fn rate_limit_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut ret: v8::ReturnValue,
+) {
+    let Some(id) = id(scope, &args, 0) else {
+        return;
+    };
+    let Some(value) = number(scope, &args, 1, "value") else {
+        return;
+    };
+    let Some(per_second) = number(scope, &args, 2, "perSecond") else {
+        return;
+    };
+
+    let state = ComputeEngine::state(scope);
+    let mut state = state.borrow_mut();
+    let fan = state.current_fan;
+    ret.set_double(
+        state
+            .helpers
+            .rate_limit(fan, &id, value, per_second, Instant::now()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clamp, curve, lerp, HelpersState};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn lerp_clamp() {
+        assert_eq!(lerp(0.25, 1.0, 0.0), 0.25);
+        assert_eq!(lerp(0.25, 1.0, 1.0), 1.0);
+        assert_eq!(lerp(0.0, 1.0, 0.25), 0.25);
+
+        assert_eq!(clamp(-1.0, 0.0, 1.0), 0.0);
+        assert_eq!(clamp(2.0, 0.0, 1.0), 1.0);
+        assert_eq!(clamp(0.5, 0.0, 1.0), 0.5);
+    }
+
+    #[test]
+    fn curve_points() {
+        let points = [(30.0, 0.25), (50.0, 0.5), (70.0, 1.0)];
+
+        assert_eq!(curve(20.0, &points), Some(0.25));
+        assert_eq!(curve(30.0, &points), Some(0.25));
+        assert_eq!(curve(40.0, &points), Some(0.375));
+        assert_eq!(curve(60.0, &points), Some(0.75));
+        assert_eq!(curve(70.0, &points), Some(1.0));
+        assert_eq!(curve(90.0, &points), Some(1.0));
+
+        assert_eq!(curve(40.0, &[(50.0, 0.5)]), Some(0.5));
+        assert_eq!(curve(60.0, &[(50.0, 0.5)]), Some(0.5));
+        assert_eq!(curve(50.0, &[(50.0, 0.25), (50.0, 0.75)]), Some(0.25));
+        assert_eq!(curve(40.0, &[]), None);
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut state = HelpersState::default();
+
+        assert_eq!(state.hysteresis(0, "cpu", 50.0, 2.0), 50.0);
+        assert_eq!(state.hysteresis(0, "cpu", 51.5, 2.0), 50.0);
+        assert_eq!(state.hysteresis(0, "cpu", 48.5, 2.0), 50.0);
+        assert_eq!(state.hysteresis(0, "cpu", 52.5, 2.0), 52.5);
+        assert_eq!(state.hysteresis(0, "cpu", 51.0, 2.0), 52.5);
+
+        // other fan and other id have own state
+        assert_eq!(state.hysteresis(1, "cpu", 40.0, 2.0), 40.0);
+        assert_eq!(state.hysteresis(0, "gpu", 40.0, 2.0), 40.0);
+        assert_eq!(state.hysteresis(0, "cpu", 51.0, 2.0), 52.5);
+    }
+
+    #[test]
+    fn ema() {
+        let mut state = HelpersState::default();
+
+        assert_eq!(state.ema(0, "cpu", 40.0, 0.5), 40.0);
+        assert_eq!(state.ema(0, "cpu", 60.0, 0.5), 50.0);
+        assert_eq!(state.ema(0, "cpu", 60.0, 0.5), 55.0);
+        assert_eq!(state.ema(1, "cpu", 60.0, 0.5), 60.0);
+
+        // alpha is clamped
+        assert_eq!(state.ema(0, "cpu", 80.0, 2.0), 80.0);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut state = HelpersState::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(state.rate_limit(0, "fan", 0.25, 0.125, at(0)), 0.25);
+        assert_eq!(state.rate_limit(0, "fan", 1.0, 0.125, at(2)), 0.5);
+        assert_eq!(state.rate_limit(0, "fan", 0.625, 0.125, at(4)), 0.625);
+        assert_eq!(state.rate_limit(0, "fan", 0.0, 0.125, at(5)), 0.5);
+        assert_eq!(state.rate_limit(1, "fan", 1.0, 0.125, at(5)), 1.0);
+    }
+}
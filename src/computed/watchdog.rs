@@ -0,0 +1,91 @@
+use deno_core::v8;
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Default)]
+struct WatchdogState {
+    deadline: Option<Instant>,
+    fired: bool,
+    stop: bool,
+}
+
+/// terminates formulas running longer than allowed
+pub struct Watchdog {
+    shared: Arc<(Mutex<WatchdogState>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn new(isolate: v8::IsolateHandle) -> Self {
+        let shared = Arc::new((Mutex::new(WatchdogState::default()), Condvar::new()));
+
+        let thread = thread::Builder::new()
+            .name("formula-watchdog".to_string())
+            .spawn({
+                let shared = shared.clone();
+                move || Self::run(&shared, &isolate)
+            })
+            .expect("cannot spawn formula watchdog thread");
+
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    fn run((state, condvar): &(Mutex<WatchdogState>, Condvar), isolate: &v8::IsolateHandle) {
+        let mut state = state.lock().unwrap();
+
+        while !state.stop {
+            let Some(deadline) = state.deadline else {
+                state = condvar.wait(state).unwrap();
+                continue;
+            };
+
+            let now = Instant::now();
+            if now >= deadline {
+                isolate.terminate_execution();
+                state.deadline = None;
+                state.fired = true;
+            } else {
+                state = condvar.wait_timeout(state, deadline - now).unwrap().0;
+            }
+        }
+    }
+
+    /// start watching execution which must finish in `timeout`
+    pub fn arm(&self, timeout: Duration) {
+        let (state, condvar) = &*self.shared;
+        let mut state = state.lock().unwrap();
+
+        state.deadline = Some(Instant::now() + timeout);
+        state.fired = false;
+        condvar.notify_one();
+    }
+
+    /// stop watching execution. returns `true` if execution was terminated
+    pub fn disarm(&self) -> bool {
+        let (state, _) = &*self.shared;
+        let mut state = state.lock().unwrap();
+
+        state.deadline = None;
+        std::mem::take(&mut state.fired)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        {
+            let (state, condvar) = &*self.shared;
+            state.lock().unwrap().stop = true;
+            condvar.notify_one();
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
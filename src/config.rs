@@ -2,6 +2,7 @@ use serde::{Deserialize, Deserializer};
 use std::{
     collections::HashMap,
     fs, io,
+    net::SocketAddr,
     path::{Path, PathBuf},
     time::Duration,
 };
@@ -16,28 +17,255 @@ pub enum ConfigSourceValue {
     Nvidia {
         name: Option<String>,
         uuid: Option<String>,
+        pci_bus_id: Option<String>,
+        /// index of device in NVML
+        index: Option<u32>,
+        #[serde(default)]
+        metric: ConfigNvidiaMetric,
     },
+    #[serde(rename = "amdgpu")]
+    Amdgpu {
+        pci_bus_id: Option<String>,
+        /// index of card among amdgpu cards
+        index: Option<u32>,
+        #[serde(default)]
+        metric: ConfigAmdgpuMetric,
+    },
+    #[serde(rename = "drive")]
+    Drive {
+        /// name in `/dev/disk/by-id`
+        id: Option<String>,
+        serial: Option<String>,
+        #[serde(default)]
+        aggregate: ConfigDriveAggregate,
+    },
+    #[serde(rename = "thermal_zone")]
+    ThermalZone {
+        /// `type` of zone
+        zone: String,
+        /// type of trip point read instead of zone temperature
+        trip: Option<String>,
+    },
+    #[serde(rename = "command")]
+    Command {
+        /// program and its arguments
+        command: Vec<String>,
+        /// value is first capture group (or whole match) in output
+        regex: Option<String>,
+        /// value is found by JSON pointer in output parsed as JSON
+        json_pointer: Option<String>,
+        #[serde(default = "ConfigSourceValue::timeout_default")]
+        #[serde(deserialize_with = "duration_ms_deserialize")]
+        timeout: Duration,
+        /// command is run in background every `interval` of main section or this if it is longer
+        #[serde(default)]
+        #[serde(deserialize_with = "duration_deserialize")]
+        min_interval: Duration,
+    },
+    #[serde(rename = "push")]
+    Push {
+        /// value older than this is error
+        #[serde(default = "ConfigSourceValue::max_age_default")]
+        #[serde(deserialize_with = "duration_deserialize")]
+        max_age: Duration,
+    },
+}
+
+impl ConfigSourceValue {
+    fn timeout_default() -> Duration {
+        Duration::from_millis(1000)
+    }
+
+    fn max_age_default() -> Duration {
+        Duration::from_secs(10)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub enum ConfigNvidiaMetric {
+    #[default]
+    #[serde(rename = "temperature")]
+    Temperature,
+    #[serde(rename = "memory_temperature")]
+    MemoryTemperature,
+    #[serde(rename = "power_watts")]
+    PowerWatts,
+    #[serde(rename = "gpu_utilization")]
+    GpuUtilization,
+    #[serde(rename = "memory_utilization")]
+    MemoryUtilization,
+    #[serde(rename = "fan_speed_percent")]
+    FanSpeedPercent,
+    #[serde(rename = "clock_sm")]
+    ClockSm,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub enum ConfigAmdgpuMetric {
+    #[default]
+    #[serde(rename = "edge")]
+    Edge,
+    #[serde(rename = "junction")]
+    Junction,
+    #[serde(rename = "memory")]
+    Memory,
+    #[serde(rename = "power_watts")]
+    PowerWatts,
+    #[serde(rename = "gpu_busy_percent")]
+    GpuBusyPercent,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub enum ConfigDriveAggregate {
+    #[default]
+    #[serde(rename = "composite")]
+    Composite,
+    #[serde(rename = "max")]
+    Max,
+    #[serde(rename = "average")]
+    Average,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub enum ConfigPwmMode {
+    #[serde(rename = "dc")]
+    Dc,
+    #[serde(rename = "pwm")]
+    Pwm,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ConfigFanMember {
+    Path(PathBuf),
+    Options {
+        path: PathBuf,
+        #[serde(default)]
+        offset: f32,
+        #[serde(default)]
+        min: f32,
+        #[serde(default = "ConfigFanMember::max_default")]
+        max: f32,
+    },
+}
+
+impl ConfigFanMember {
+    fn max_default() -> f32 {
+        1.0
+    }
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
 #[serde(tag = "type")]
 pub enum ConfigFanTarget {
     #[serde(rename = "pwm")]
-    Pwm { path: PathBuf },
+    Pwm {
+        path: Option<PathBuf>,
+        #[serde(default)]
+        paths: Vec<ConfigFanMember>,
+        glob: Option<String>,
+        mode: Option<ConfigPwmMode>,
+        #[serde(default = "ConfigFanTarget::enable_value_default")]
+        enable_value: u8,
+        #[serde(default = "ConfigFanTarget::enable_check_default")]
+        #[serde(deserialize_with = "duration_deserialize")]
+        enable_check: Duration,
+        #[serde(default)]
+        min_delta: u8,
+        #[serde(default = "ConfigFanTarget::refresh_default")]
+        #[serde(deserialize_with = "duration_deserialize")]
+        refresh: Duration,
+    },
+    #[serde(rename = "nvidia")]
+    Nvidia {
+        /// name of card
+        device: Option<String>,
+        uuid: Option<String>,
+        pci_bus_id: Option<String>,
+        /// index of device in NVML
+        index: Option<u32>,
+        /// indexes of fans of card. all fans by default
+        fans: Option<Vec<u32>>,
+        #[serde(default = "ConfigFanTarget::refresh_default")]
+        #[serde(deserialize_with = "duration_deserialize")]
+        refresh: Duration,
+    },
+    #[serde(rename = "amdgpu")]
+    Amdgpu {
+        pci_bus_id: Option<String>,
+        /// index of card among amdgpu cards
+        index: Option<u32>,
+        #[serde(default = "ConfigFanTarget::enable_check_default")]
+        #[serde(deserialize_with = "duration_deserialize")]
+        enable_check: Duration,
+        #[serde(default)]
+        min_delta: u8,
+        #[serde(default = "ConfigFanTarget::refresh_default")]
+        #[serde(deserialize_with = "duration_deserialize")]
+        refresh: Duration,
+    },
+}
+
+impl ConfigFanTarget {
+    fn enable_value_default() -> u8 {
+        1
+    }
+
+    fn enable_check_default() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn refresh_default() -> Duration {
+        Duration::from_secs(30)
+    }
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
 pub struct ConfigFan {
+    /// name used in logs
+    pub name: Option<String>,
     pub value: String,
+    #[serde(default = "ConfigFan::timeout_default")]
+    #[serde(deserialize_with = "duration_ms_deserialize")]
+    pub timeout: Duration,
+    #[serde(default = "ConfigFan::failsafe_default")]
+    pub failsafe: f32,
     #[serde(flatten)]
     pub target: ConfigFanTarget,
 }
 
+impl ConfigFan {
+    /// name used in logs, `fan[index]` by default
+    pub fn display_name(&self, index: usize) -> String {
+        self.name.clone().unwrap_or_else(|| format!("fan[{index}]"))
+    }
+
+    fn timeout_default() -> Duration {
+        Duration::from_millis(50)
+    }
+
+    fn failsafe_default() -> f32 {
+        1.0
+    }
+}
+
 #[derive(Debug, PartialEq, Deserialize)]
 pub struct ConfigMain {
     #[serde(default = "ConfigMain::interval_default")]
-    #[serde(deserialize_with = "ConfigMain::interval_deserialize")]
+    #[serde(deserialize_with = "duration_deserialize")]
     pub interval: Duration,
+    /// files with helpers for formulas. relative paths are resolved from directory of config
+    #[serde(default)]
+    pub scripts: Vec<PathBuf>,
+    /// count of samples kept in history of every source
+    #[serde(default = "ConfigMain::history_default")]
+    pub history: usize,
+    /// path to NVML library. `libnvidia-ml.so.1` is tried if it cannot be loaded
+    pub nvidia_library: Option<String>,
+    /// unix socket for values of push sources
+    pub push_socket: Option<PathBuf>,
+    /// loopback address of HTTP endpoint for values of push sources
+    pub push_http: Option<SocketAddr>,
 }
 
 impl ConfigMain {
@@ -45,18 +273,38 @@ impl ConfigMain {
         Duration::from_secs(2)
     }
 
-    fn interval_deserialize<'de, D>(d: D) -> Result<Duration, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let value = Deserialize::deserialize(d)?;
-        Ok(Duration::from_secs(value))
+    fn history_default() -> usize {
+        60
     }
 }
 
+/// deserialize [`Duration`] from seconds
+fn duration_deserialize<'de, D>(d: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Deserialize::deserialize(d)?;
+    Ok(Duration::from_secs(value))
+}
+
+/// deserialize [`Duration`] from milliseconds
+fn duration_ms_deserialize<'de, D>(d: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Deserialize::deserialize(d)?;
+    Ok(Duration::from_millis(value))
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct ConfigShared {
+    pub script: String,
+}
+
 #[derive(Debug, PartialEq, Deserialize)]
 pub struct Config {
     pub main: ConfigMain,
+    pub shared: Option<ConfigShared>,
     #[serde(rename = "source")]
     pub sources: HashMap<String, ConfigSourceValue>,
     #[serde(rename = "fan")]
@@ -76,8 +324,13 @@ impl Config {
     where
         P: AsRef<Path>,
     {
-        let root = fs::read_to_string(path)?;
-        let config: Self = toml::from_str(&root)?;
+        let root = fs::read_to_string(&path)?;
+        let mut config: Self = toml::from_str(&root)?;
+
+        let base = path.as_ref().parent().unwrap_or(Path::new(""));
+        for script in config.main.scripts.iter_mut() {
+            *script = base.join(&script);
+        }
 
         Ok(config)
     }
@@ -99,13 +352,24 @@ impl From<toml::de::Error> for ConfigReadError {
 mod test {
     use std::{path::PathBuf, time::Duration};
 
-    use crate::config::{Config, ConfigFanTarget, ConfigSourceValue};
+    use crate::config::{
+        Config, ConfigAmdgpuMetric, ConfigDriveAggregate, ConfigFanMember, ConfigFanTarget,
+        ConfigNvidiaMetric, ConfigPwmMode, ConfigShared, ConfigSourceValue,
+    };
 
     #[test]
     fn parse() {
         const CONF: &str = r#"
 [main]
 interval = 123
+history = 30
+nvidia_library = "/opt/nvidia/lib/libnvidia-ml.so"
+push_socket = "/run/fand/push.sock"
+push_http = "127.0.0.1:9410"
+scripts = ["lib.js", "/etc/fand/curves.mjs"]
+
+[shared]
+script = "function helper() {}"
 
 [source.s1]
 type = "file"
@@ -117,27 +381,129 @@ type = "nvidia"
 [source.s3]
 type = "nvidia"
 name = "NVIDIA GeForce RTX 4090"
+pci_bus_id = "0000:01:00.0"
 
 [source.s4]
 type = "nvidia"
 uuid = "GPU-23eda959-34a7-4abf-8e19-9c0beded366e"
+metric = "power_watts"
 
 [source.s5]
 type = "file"
 factor = 0.1
 path = "/value2"
 
+[source.s6]
+type = "amdgpu"
+pci_bus_id = "0000:03:00.0"
+metric = "junction"
+
+[source.s7]
+type = "drive"
+serial = "S5GXNF0R654321"
+aggregate = "max"
+
+[source.s8]
+type = "drive"
+id = "ata-Samsung_SSD_860_EVO_500GB_S3Z2NB0K123456"
+
+[source.s9]
+type = "thermal_zone"
+zone = "x86_pkg_temp"
+trip = "critical"
+
+[source.s10]
+type = "command"
+command = ["sensors", "-j"]
+json_pointer = "/k10temp-pci-00c3/Tctl/temp1_input"
+min_interval = 5
+
+[source.s11]
+type = "command"
+command = ["ipmitool", "sdr", "get", "Inlet Temp"]
+regex = 'Sensor Reading\s*:\s*([0-9.]+)'
+timeout = 3000
+
+[source.s12]
+type = "push"
+max_age = 30
+
+[source.s13]
+type = "push"
+
 [[fan]]
 type = "pwm"
 value = "s3"
 path = "/pwm"
+
+[[fan]]
+name = "cpu"
+type = "pwm"
+value = "s1"
+path = "/pwm2"
+timeout = 100
+failsafe = 0.6
+enable_check = 60
+mode = "dc"
+enable_value = 5
+min_delta = 3
+refresh = 120
+
+[[fan]]
+type = "pwm"
+value = "s2"
+paths = ["/pwm3", { path = "/pwm4", offset = 0.1, max = 0.8 }]
+
+[[fan]]
+type = "pwm"
+value = "s2"
+glob = "/hwmon*/pwm[1-3]"
+
+[[fan]]
+name = "gpu"
+type = "nvidia"
+value = "s4"
+index = 1
+fans = [0, 1]
+
+[[fan]]
+type = "amdgpu"
+value = "s6"
+min_delta = 2
 "#;
         let config: Config = toml::from_str(CONF).unwrap();
 
-        assert_eq!(config.sources.len(), 5);
-        assert_eq!(config.fans.len(), 1);
+        assert_eq!(config.sources.len(), 13);
+        assert_eq!(config.fans.len(), 6);
 
         assert_eq!(config.main.interval, Duration::from_secs(123));
+        assert_eq!(config.main.history, 30);
+        assert_eq!(
+            config.main.nvidia_library.as_deref(),
+            Some("/opt/nvidia/lib/libnvidia-ml.so")
+        );
+        assert_eq!(
+            config.main.push_socket,
+            Some(PathBuf::from("/run/fand/push.sock"))
+        );
+        assert_eq!(
+            config.main.push_http,
+            Some("127.0.0.1:9410".parse().unwrap())
+        );
+        assert_eq!(
+            config.main.scripts,
+            vec![
+                PathBuf::from("lib.js"),
+                PathBuf::from("/etc/fand/curves.mjs")
+            ]
+        );
+
+        assert_eq!(
+            config.shared,
+            Some(ConfigShared {
+                script: "function helper() {}".to_string()
+            })
+        );
 
         assert!(config.sources.contains_key("s1"));
         assert_eq!(
@@ -153,7 +519,10 @@ path = "/pwm"
             config.sources["s2"],
             ConfigSourceValue::Nvidia {
                 name: None,
-                uuid: None
+                uuid: None,
+                pci_bus_id: None,
+                index: None,
+                metric: ConfigNvidiaMetric::Temperature,
             }
         );
 
@@ -162,7 +531,10 @@ path = "/pwm"
             config.sources["s3"],
             ConfigSourceValue::Nvidia {
                 name: Some("NVIDIA GeForce RTX 4090".to_string()),
-                uuid: None
+                uuid: None,
+                pci_bus_id: Some("0000:01:00.0".to_string()),
+                index: None,
+                metric: ConfigNvidiaMetric::Temperature,
             }
         );
 
@@ -172,6 +544,9 @@ path = "/pwm"
             ConfigSourceValue::Nvidia {
                 name: None,
                 uuid: Some("GPU-23eda959-34a7-4abf-8e19-9c0beded366e".to_string()),
+                pci_bus_id: None,
+                index: None,
+                metric: ConfigNvidiaMetric::PowerWatts,
             }
         );
 
@@ -184,11 +559,164 @@ path = "/pwm"
             }
         );
 
+        assert_eq!(config.fans[0].name, None);
         assert_eq!(config.fans[0].value, "s3");
+        assert_eq!(config.fans[0].timeout, Duration::from_millis(50));
+        assert_eq!(config.fans[0].failsafe, 1.0);
         assert_eq!(
             config.fans[0].target,
             ConfigFanTarget::Pwm {
-                path: PathBuf::from("/pwm")
+                path: Some(PathBuf::from("/pwm")),
+                paths: vec![],
+                glob: None,
+                mode: None,
+                enable_value: 1,
+                enable_check: Duration::from_secs(10),
+                min_delta: 0,
+                refresh: Duration::from_secs(30),
+            }
+        );
+
+        assert_eq!(config.fans[1].name, Some("cpu".to_string()));
+        assert_eq!(config.fans[1].value, "s1");
+        assert_eq!(config.fans[1].timeout, Duration::from_millis(100));
+        assert_eq!(config.fans[1].failsafe, 0.6);
+        assert_eq!(
+            config.fans[1].target,
+            ConfigFanTarget::Pwm {
+                path: Some(PathBuf::from("/pwm2")),
+                paths: vec![],
+                glob: None,
+                mode: Some(ConfigPwmMode::Dc),
+                enable_value: 5,
+                enable_check: Duration::from_secs(60),
+                min_delta: 3,
+                refresh: Duration::from_secs(120),
+            }
+        );
+
+        assert_eq!(
+            config.fans[2].target,
+            ConfigFanTarget::Pwm {
+                path: None,
+                paths: vec![
+                    ConfigFanMember::Path(PathBuf::from("/pwm3")),
+                    ConfigFanMember::Options {
+                        path: PathBuf::from("/pwm4"),
+                        offset: 0.1,
+                        min: 0.0,
+                        max: 0.8,
+                    },
+                ],
+                glob: None,
+                mode: None,
+                enable_value: 1,
+                enable_check: Duration::from_secs(10),
+                min_delta: 0,
+                refresh: Duration::from_secs(30),
+            }
+        );
+
+        assert_eq!(
+            config.fans[3].target,
+            ConfigFanTarget::Pwm {
+                path: None,
+                paths: vec![],
+                glob: Some("/hwmon*/pwm[1-3]".to_string()),
+                mode: None,
+                enable_value: 1,
+                enable_check: Duration::from_secs(10),
+                min_delta: 0,
+                refresh: Duration::from_secs(30),
+            }
+        );
+
+        assert_eq!(config.fans[4].name, Some("gpu".to_string()));
+        assert_eq!(
+            config.fans[4].target,
+            ConfigFanTarget::Nvidia {
+                device: None,
+                uuid: None,
+                pci_bus_id: None,
+                index: Some(1),
+                fans: Some(vec![0, 1]),
+                refresh: Duration::from_secs(30),
+            }
+        );
+
+        assert_eq!(
+            config.sources["s6"],
+            ConfigSourceValue::Amdgpu {
+                pci_bus_id: Some("0000:03:00.0".to_string()),
+                index: None,
+                metric: ConfigAmdgpuMetric::Junction,
+            }
+        );
+        assert_eq!(
+            config.sources["s7"],
+            ConfigSourceValue::Drive {
+                id: None,
+                serial: Some("S5GXNF0R654321".to_string()),
+                aggregate: ConfigDriveAggregate::Max,
+            }
+        );
+        assert_eq!(
+            config.sources["s8"],
+            ConfigSourceValue::Drive {
+                id: Some("ata-Samsung_SSD_860_EVO_500GB_S3Z2NB0K123456".to_string()),
+                serial: None,
+                aggregate: ConfigDriveAggregate::Composite,
+            }
+        );
+        assert_eq!(
+            config.sources["s9"],
+            ConfigSourceValue::ThermalZone {
+                zone: "x86_pkg_temp".to_string(),
+                trip: Some("critical".to_string()),
+            }
+        );
+        assert_eq!(
+            config.sources["s10"],
+            ConfigSourceValue::Command {
+                command: vec!["sensors".to_string(), "-j".to_string()],
+                regex: None,
+                json_pointer: Some("/k10temp-pci-00c3/Tctl/temp1_input".to_string()),
+                timeout: Duration::from_millis(1000),
+                min_interval: Duration::from_secs(5),
+            }
+        );
+        assert_eq!(
+            config.sources["s11"],
+            ConfigSourceValue::Command {
+                command: ["ipmitool", "sdr", "get", "Inlet Temp"]
+                    .map(String::from)
+                    .to_vec(),
+                regex: Some(r"Sensor Reading\s*:\s*([0-9.]+)".to_string()),
+                json_pointer: None,
+                timeout: Duration::from_millis(3000),
+                min_interval: Duration::ZERO,
+            }
+        );
+        assert_eq!(
+            config.sources["s12"],
+            ConfigSourceValue::Push {
+                max_age: Duration::from_secs(30),
+            }
+        );
+        assert_eq!(
+            config.sources["s13"],
+            ConfigSourceValue::Push {
+                max_age: Duration::from_secs(10),
+            }
+        );
+        assert_eq!(
+            config.fans[5].target,
+            ConfigFanTarget::Amdgpu {
+                pci_bus_id: None,
+                index: None,
+                enable_check: Duration::from_secs(10),
+                min_delta: 2,
+                refresh: Duration::from_secs(30),
             }
         );
     }
@@ -1,8 +1,12 @@
 use std::{error::Error, fmt};
 
+mod group;
+mod nvidia;
 mod pwm;
 
-pub use pwm::FanPwm;
+pub use group::{FanGroup, FanGroupMember};
+pub use nvidia::FanNvidia;
+pub use pwm::{FanPwm, FanPwmOptions, PwmMode};
 
 /// power of fan
 #[derive(Clone, Copy)]
@@ -19,8 +23,9 @@ impl From<u8> for FanPower {
 }
 
 impl FanPower {
-    pub fn full_speed() -> Self {
-        Self(255u8)
+    /// power in range `0.0..=1.0`
+    pub fn fraction(self) -> f64 {
+        self.0 as f64 / 255.0
     }
 }
 
@@ -0,0 +1,103 @@
+use super::{Fan, FanPower};
+use std::error::Error;
+use thiserror::Error;
+
+/// fan of group with own power range
+pub struct FanGroupMember {
+    pub fan: Box<dyn Fan>,
+    /// added to power of group
+    pub offset: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// several fans driven by one power
+pub struct FanGroup {
+    members: Vec<FanGroupMember>,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FanGroupError {
+    #[error("`min` {min} and `max` {max} must be in range 0..=1 and `min` must not exceed `max`")]
+    Range { min: f32, max: f32 },
+    #[error("`offset` {0} is not finite")]
+    Offset(f32),
+}
+
+impl FanGroup {
+    pub fn new(members: Vec<FanGroupMember>) -> Self {
+        Self { members }
+    }
+}
+
+impl FanGroupMember {
+    /// check options of member before its fan is taken over
+    pub fn check(offset: f32, min: f32, max: f32) -> Result<(), FanGroupError> {
+        if !(0.0 <= min && min <= max && max <= 1.0) {
+            return Err(FanGroupError::Range { min, max });
+        }
+        if !offset.is_finite() {
+            return Err(FanGroupError::Offset(offset));
+        }
+
+        Ok(())
+    }
+
+    fn power(&self, power: FanPower) -> FanPower {
+        let power = power.0 as f32 / 255.0 + self.offset;
+        let power = power.clamp(self.min, self.max).clamp(0.0, 1.0);
+
+        FanPower::from((power * 255.0) as u8)
+    }
+}
+
+impl Fan for FanGroup {
+    fn try_set_power(&mut self, power: FanPower) -> Result<(), Box<dyn Error>> {
+        let mut errors = Vec::new();
+
+        for (index, member) in self.members.iter_mut().enumerate() {
+            let power = member.power(power);
+            if let Err(err) = member.fan.try_set_power(power) {
+                errors.push(format!("member {index}: {err}"));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; ").into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FanGroupError, FanGroupMember};
+
+    #[test]
+    fn check() {
+        assert_eq!(FanGroupMember::check(0.1, 0.2, 0.8), Ok(()));
+        assert_eq!(FanGroupMember::check(-1.0, 0.0, 0.0), Ok(()));
+
+        for (min, max) in [
+            (0.8, 0.2),
+            (-0.1, 0.5),
+            (0.0, 1.5),
+            (f32::NAN, 1.0),
+            (0.0, f32::NAN),
+        ] {
+            assert!(matches!(
+                FanGroupMember::check(0.0, min, max),
+                Err(FanGroupError::Range { .. })
+            ));
+        }
+        assert!(matches!(
+            FanGroupMember::check(f32::NAN, 0.0, 1.0),
+            Err(FanGroupError::Offset(_))
+        ));
+        assert!(matches!(
+            FanGroupMember::check(f32::INFINITY, 0.0, 1.0),
+            Err(FanGroupError::Offset(_))
+        ));
+    }
+}
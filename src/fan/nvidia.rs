@@ -0,0 +1,241 @@
+use super::{Fan, FanPower};
+use crate::nvidia::{NvidiaDevice, SourceNvidiaError};
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+/// fans of nvidia card. default fan policy is restored on drop
+pub struct FanNvidia {
+    dev: NvidiaDevice,
+    /// configured fans, all fans of device if `None`
+    fans: Option<Vec<u32>>,
+    /// interval for setting speed even if it's not changed
+    refresh: Duration,
+    /// read from device on first use and again after errors and reinit of NVML
+    setup: Option<FanNvidiaSetup>,
+    /// fans which speed was set, their default policy is restored on drop
+    controlled: Vec<u32>,
+    last_speed: Option<(u32, Instant)>,
+}
+
+struct FanNvidiaSetup {
+    fans: Vec<u32>,
+    /// allowed range of speed in percents
+    min: u32,
+    max: u32,
+    /// generation of NVML which fans were set up in
+    generation: Option<u64>,
+}
+
+impl FanNvidia {
+    /// control `fans` of device, all fans of device if `None`
+    pub fn new(dev: NvidiaDevice, fans: Option<Vec<u32>>, refresh: Duration) -> Self {
+        Self {
+            dev,
+            fans,
+            refresh,
+            setup: None,
+            controlled: Vec::new(),
+            last_speed: None,
+        }
+    }
+
+    fn try_setup(
+        dev: &NvidiaDevice,
+        fans: Option<Vec<u32>>,
+    ) -> Result<FanNvidiaSetup, SourceNvidiaError> {
+        let count = dev.try_get_fan_count()?;
+        let fans = fans.unwrap_or_else(|| (0..count).collect());
+        if let Some(&fan) = fans.iter().find(|&&fan| fan >= count) {
+            return Err(SourceNvidiaError::FanNotFound { fan, count });
+        }
+
+        let (min, max) = dev.try_get_min_max_fan_speed()?;
+
+        log::info!("Controlling fans {fans:?} of {dev} in range {min}..={max}%");
+
+        Ok(FanNvidiaSetup {
+            fans,
+            min,
+            max,
+            generation: dev.generation(),
+        })
+    }
+
+    fn set_power(&mut self, power: FanPower) -> Result<(), SourceNvidiaError> {
+        let generation = self.dev.generation();
+        if let Some(setup) = &self.setup {
+            // driver could reset fans while gpu was lost
+            if setup.generation != generation {
+                log::info!("{}: NVML was reinitialised, setting fans again", self.dev);
+                self.setup = None;
+                self.last_speed = None;
+            }
+        }
+
+        let setup = match &self.setup {
+            Some(setup) => setup,
+            None => self
+                .setup
+                .insert(Self::try_setup(&self.dev, self.fans.clone())?),
+        };
+
+        let speed = setup.speed(power);
+        if let Some((last_speed, time)) = self.last_speed {
+            if last_speed == speed && time.elapsed() < self.refresh {
+                return Ok(());
+            }
+        }
+
+        // forget last speed until all fans accept it
+        self.last_speed = None;
+        for &fan in &setup.fans {
+            if !self.controlled.contains(&fan) {
+                self.controlled.push(fan);
+            }
+            self.dev.try_set_fan_speed(fan, speed)?;
+        }
+        self.last_speed = Some((speed, Instant::now()));
+
+        Ok(())
+    }
+}
+
+impl FanNvidiaSetup {
+    fn speed(&self, power: FanPower) -> u32 {
+        let speed = (power.0 as u32 * 100 + 127) / 255;
+        speed.clamp(self.min, self.max)
+    }
+}
+
+impl Fan for FanNvidia {
+    fn try_set_power(&mut self, power: FanPower) -> Result<(), Box<dyn Error>> {
+        let result = self.set_power(power);
+        if result.is_err() {
+            // fans could be reset by failed call, set them up again on next call
+            self.setup = None;
+            self.last_speed = None;
+        }
+
+        Ok(result?)
+    }
+}
+
+impl Drop for FanNvidia {
+    fn drop(&mut self) {
+        for &fan in &self.controlled {
+            if let Err(err) = self.dev.try_set_default_fan_speed(fan) {
+                log::error!(
+                    "cannot restore default policy of fan {fan} of {}: {err}",
+                    self.dev
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FanNvidia;
+    use crate::{
+        fan::{Fan, FanPower},
+        nvidia::{
+            mock::{MockDevice, MockNvml, MockState},
+            Nvidia, NvidiaSelector, Nvml,
+        },
+    };
+    use std::{cell::RefCell, rc::Rc, thread, time::Duration};
+
+    fn fan(fans: Option<Vec<u32>>, refresh: Duration) -> (FanNvidia, Rc<RefCell<MockState>>) {
+        let mut dev = MockDevice::new("NVIDIA GeForce RTX 4090", "GPU-0");
+        dev.min_max_fan_speed = (30, 90);
+        let api = MockNvml::new(vec![dev]);
+        let state = api.state();
+        let nvidia = Rc::new(Nvidia::new(Box::new(move || {
+            Ok(Box::new(api.clone()) as Box<dyn Nvml>)
+        })));
+
+        let dev = nvidia.device(NvidiaSelector::default());
+        (FanNvidia::new(dev, fans, refresh), state)
+    }
+
+    fn speeds(state: &RefCell<MockState>) -> Vec<Option<u32>> {
+        state.borrow().devices[0].fans.clone()
+    }
+
+    #[test]
+    fn speed() {
+        let (mut fan, state) = fan(None, Duration::from_secs(60));
+
+        fan.try_set_power(FanPower(128)).unwrap();
+        assert_eq!(speeds(&state), [Some(50), Some(50)]);
+
+        // clamped to range of card
+        fan.try_set_power(FanPower(0)).unwrap();
+        assert_eq!(speeds(&state), [Some(30), Some(30)]);
+        fan.try_set_power(FanPower(255)).unwrap();
+        assert_eq!(speeds(&state), [Some(90), Some(90)]);
+
+        drop(fan);
+        assert_eq!(speeds(&state), [None, None]);
+    }
+
+    #[test]
+    fn fan_not_found() {
+        let (mut fan, state) = fan(Some(vec![1, 2]), Duration::from_secs(60));
+
+        assert_eq!(
+            fan.try_set_power(FanPower(128)).unwrap_err().to_string(),
+            "fan 2 is not found, card has 2 fans"
+        );
+        assert_eq!(speeds(&state), [None, None]);
+    }
+
+    #[test]
+    fn reinit() {
+        let (mut fan, state) = fan(Some(vec![1]), Duration::from_secs(60));
+
+        fan.try_set_power(FanPower(128)).unwrap();
+        assert_eq!(speeds(&state), [None, Some(50)]);
+
+        // lost gpu is found by source of same card, driver resets fans
+        state.borrow_mut().devices[0].lost = true;
+        fan.dev.try_get_temperature().unwrap_err();
+        state.borrow_mut().devices[0].lost = false;
+        state.borrow_mut().devices[0].fans[1] = None;
+
+        fan.try_set_power(FanPower(128)).unwrap();
+        assert_eq!(speeds(&state), [None, Some(50)]);
+        assert_eq!(state.borrow().inits, 2);
+
+        // lost gpu is found by fan
+        state.borrow_mut().devices[0].lost = true;
+        fan.try_set_power(FanPower(0)).unwrap_err();
+        state.borrow_mut().devices[0].lost = false;
+        state.borrow_mut().devices[0].fans[1] = None;
+
+        fan.try_set_power(FanPower(128)).unwrap();
+        assert_eq!(speeds(&state), [None, Some(50)]);
+        assert_eq!(state.borrow().inits, 3);
+
+        drop(fan);
+        assert_eq!(speeds(&state), [None, None]);
+    }
+
+    #[test]
+    fn refresh() {
+        let (mut fan, state) = fan(Some(vec![0]), Duration::from_millis(100));
+
+        fan.try_set_power(FanPower(128)).unwrap();
+        state.borrow_mut().devices[0].fans[0] = None;
+
+        // same speed is not set again until refresh
+        fan.try_set_power(FanPower(128)).unwrap();
+        assert_eq!(speeds(&state), [None, None]);
+
+        thread::sleep(Duration::from_millis(150));
+        fan.try_set_power(FanPower(128)).unwrap();
+        assert_eq!(speeds(&state), [Some(50), None]);
+    }
+}
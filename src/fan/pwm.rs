@@ -4,22 +4,59 @@ use std::{
     fs::File,
     io::{self, Read as _, Seek as _, Write as _},
     path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
-struct PwmEnable {
+/// wall clock running ahead of monotonic clock by more than this means the system was suspended
+const RESUME_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// value of `pwmN_mode`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PwmMode {
+    Dc,
+    Pwm,
+}
+
+#[derive(Clone)]
+pub struct FanPwmOptions {
+    /// value written to `pwmN_enable`
+    pub enable_value: u8,
+    /// value written to `pwmN_mode`. `pwmN_mode` is untouched if `None`
+    pub mode: Option<PwmMode>,
+    /// interval for checking that `pwmN_enable` still holds `enable_value`
+    pub enable_check: Duration,
+    /// changes of `pwmN` smaller than this are not written
+    pub min_delta: u8,
+    /// interval for rewriting `pwmN` even if it's not changed
+    pub refresh: Duration,
+}
+
+/// sysfs attribute which is set while fan is controlled and restored on drop
+struct PwmAttribute {
+    path: PathBuf,
     file: File,
-    original: [u8; 4],
+    original: Vec<u8>,
+    value: String,
 }
 
 struct InnerFanPwm {
     file: File,
-    _enable: PwmEnable,
+    enable: PwmAttribute,
+    _mode: Option<PwmAttribute>,
 }
 
 pub struct FanPwm {
     pwm_path: PathBuf,
-    pwm_enable_path: PathBuf,
+    options: FanPwmOptions,
     inner: Option<InnerFanPwm>,
+    last_check: Instant,
+    last_tick: Option<(Instant, SystemTime)>,
+    last_write: Option<(FanPower, Instant)>,
+}
+
+fn file_read(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    file.seek(io::SeekFrom::Start(0))?;
+    file.read(buf)
 }
 
 fn file_write(file: &mut File, data: &[u8]) -> io::Result<()> {
@@ -29,23 +66,90 @@ fn file_write(file: &mut File, data: &[u8]) -> io::Result<()> {
 }
 
 impl FanPwm {
-    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+    pub fn new(path: impl AsRef<Path>, options: FanPwmOptions) -> io::Result<Self> {
         let pwm_path = PathBuf::from(path.as_ref());
-        let pwm_enable_path = PwmEnable::path_to_pwm_enable(path).unwrap();
-        let inner = InnerFanPwm::new(&pwm_path, &pwm_enable_path)?;
+        let inner = InnerFanPwm::new(&pwm_path, &options)?;
 
         Ok(Self {
             pwm_path,
-            pwm_enable_path,
+            options,
             inner: Some(inner),
+            last_check: Instant::now(),
+            last_tick: None,
+            last_write: None,
         })
     }
 
+    fn need_write(&self, power: FanPower) -> bool {
+        let Some((last, time)) = self.last_write else {
+            return true;
+        };
+
+        let delta = last.0.abs_diff(power.0);
+        let extreme = power.0 == 0 || power.0 == u8::MAX;
+
+        delta != 0 && (delta >= self.options.min_delta || extreme)
+            || time.elapsed() >= self.options.refresh
+    }
+
+    /// detect resume from suspend by comparing wall clock and monotonic clock since last tick
+    fn resumed(&mut self) -> bool {
+        let now = (Instant::now(), SystemTime::now());
+        let Some((mono, wall)) = self.last_tick.replace(now) else {
+            return false;
+        };
+
+        let mono = now.0.duration_since(mono);
+        let wall = now.1.duration_since(wall).unwrap_or_default();
+
+        if wall > mono + RESUME_THRESHOLD {
+            log::info!(
+                "{:?}: clock jumped by {:?}, assuming resume from suspend",
+                self.pwm_path,
+                wall - mono
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    /// make sure `pwmN_enable` still holds configured value and take control back otherwise
+    fn check_enable(&mut self) -> io::Result<()> {
+        self.last_check = Instant::now();
+
+        let Some(inner) = self.inner.as_mut() else {
+            // `pwmN_enable` will be set while reopening
+            return Ok(());
+        };
+
+        let enable = &mut inner.enable;
+        let ret = enable.read().and_then(|value| {
+            if value == enable.value {
+                return Ok(());
+            }
+
+            log::warn!(
+                "{:?} was changed to {value:?} by firmware or another tool. Taking control back",
+                enable.path
+            );
+            // firmware could change `pwmN` too
+            self.last_write = None;
+            enable.write()
+        });
+
+        if ret.is_err() {
+            self.inner.take();
+            self.last_write = None;
+        }
+        ret
+    }
+
     fn file_write(&mut self, buf: &[u8]) -> io::Result<()> {
         let file = match &mut self.inner {
             Some(ref mut inner) => &mut inner.file,
             None => {
-                self.inner = Some(InnerFanPwm::new(&self.pwm_path, &self.pwm_enable_path)?);
+                self.inner = Some(InnerFanPwm::new(&self.pwm_path, &self.options)?);
 
                 unsafe { &mut self.inner.as_mut().unwrap_unchecked().file }
             }
@@ -54,58 +158,169 @@ impl FanPwm {
         let ret = file_write(file, buf);
         if ret.is_err() {
             self.inner.take();
+            self.last_write = None;
         }
         ret
     }
 }
 
 impl InnerFanPwm {
-    fn new(pwm: impl AsRef<Path>, pwm_enable: impl AsRef<Path>) -> io::Result<Self> {
+    fn new(pwm: impl AsRef<Path>, options: &FanPwmOptions) -> io::Result<Self> {
         let file = File::options().write(true).truncate(true).open(&pwm)?;
-        let enable = PwmEnable::new(pwm_enable)?;
+
+        let mode = match options.mode {
+            Some(mode) => {
+                let value = match mode {
+                    PwmMode::Dc => 0,
+                    PwmMode::Pwm => 1,
+                };
+                let path = PwmAttribute::path_for(&pwm, "_mode").unwrap();
+                Some(PwmAttribute::new(path, value)?)
+            }
+            None => None,
+        };
+
+        let path = PwmAttribute::path_for(&pwm, "_enable").unwrap();
+        let enable = PwmAttribute::new(path, options.enable_value)?;
 
         Ok(Self {
             file,
-            _enable: enable,
+            enable,
+            _mode: mode,
         })
     }
 }
 
-impl PwmEnable {
-    fn new(path: impl AsRef<Path>) -> io::Result<Self> {
-        let mut file = File::options().write(true).read(true).open(path)?;
-        let mut original = [0u8; 4];
+impl PwmAttribute {
+    /// write `value` and check that the driver accepts it
+    fn new(path: PathBuf, value: u8) -> io::Result<Self> {
+        let mut file = File::options().write(true).read(true).open(&path)?;
+        let mut buf = [0u8; 16];
+        let size = file_read(&mut file, &mut buf)?;
+        let original = Vec::from(buf[..size].trim_ascii());
 
-        file.read(&mut original)?;
+        let mut attr = Self {
+            path,
+            file,
+            original,
+            value: value.to_string(),
+        };
 
-        file_write(&mut file, &[0x31])?;
+        attr.write().map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("cannot write {:?} to {:?}: {err}", attr.value, attr.path),
+            )
+        })?;
 
-        Ok(Self { file, original })
+        let actual = attr.read()?;
+        if actual != attr.value {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{:?} does not accept {:?} (reads back {actual:?})",
+                    attr.path, attr.value
+                ),
+            ));
+        }
+
+        Ok(attr)
+    }
+
+    fn read(&mut self) -> io::Result<String> {
+        let mut buf = [0u8; 16];
+        let size = file_read(&mut self.file, &mut buf)?;
+
+        Ok(String::from_utf8_lossy(buf[..size].trim_ascii()).into_owned())
     }
 
-    fn path_to_pwm_enable(path: impl AsRef<Path>) -> Option<PathBuf> {
+    fn write(&mut self) -> io::Result<()> {
+        file_write(&mut self.file, self.value.as_bytes())
+    }
+
+    fn path_for(pwm: impl AsRef<Path>, suffix: &str) -> Option<PathBuf> {
         let pwm_name =
-            unsafe { std::str::from_utf8_unchecked(path.as_ref().file_name()?.as_encoded_bytes()) };
+            unsafe { std::str::from_utf8_unchecked(pwm.as_ref().file_name()?.as_encoded_bytes()) };
 
-        Some(
-            path.as_ref()
-                .with_file_name(pwm_name.to_string() + "_enable"),
-        )
+        Some(pwm.as_ref().with_file_name(pwm_name.to_string() + suffix))
     }
 }
 
-impl Drop for PwmEnable {
+impl Drop for PwmAttribute {
     fn drop(&mut self) {
         if let Err(e) = file_write(&mut self.file, &self.original) {
-            log::error!("cannot disable pwm: {e}");
+            log::error!("cannot restore {:?}: {e}", self.path);
         }
     }
 }
 
 impl Fan for FanPwm {
     fn try_set_power(&mut self, power: FanPower) -> Result<(), Box<dyn Error>> {
+        if self.resumed() || self.last_check.elapsed() >= self.options.enable_check {
+            self.check_enable()?;
+        }
+
+        if !self.need_write(power) {
+            log::trace!("{:?}: skip writing {power:#}", self.pwm_path);
+            return Ok(());
+        }
+
         self.file_write(format!("{}", power.0).as_bytes())?;
+        self.last_write = Some((power, Instant::now()));
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{FanPwm, FanPwmOptions};
+    use crate::{
+        fan::{Fan, FanPower},
+        test_dir::TestDir,
+    };
+    use std::{fs, thread, time::Duration};
+
+    #[test]
+    fn need_write() {
+        let root = TestDir::new("pwm");
+        let hwmon = root.write("hwmon", &[("pwm1", "0\n"), ("pwm1_enable", "2\n")]);
+        let options = FanPwmOptions {
+            enable_value: 1,
+            mode: None,
+            enable_check: Duration::from_secs(60),
+            min_delta: 3,
+            refresh: Duration::from_millis(200),
+        };
+        let mut fan = FanPwm::new(hwmon.join("pwm1"), options).unwrap();
+
+        // value written by `fan` or `-` if nothing is written
+        let mut set = |power| {
+            fs::write(hwmon.join("pwm1"), "-").unwrap();
+            fan.try_set_power(FanPower(power)).unwrap();
+            fs::read_to_string(hwmon.join("pwm1")).unwrap()
+        };
+
+        assert_eq!(set(100), "100");
+        assert_eq!(set(100), "-");
+        assert_eq!(set(102), "-");
+        assert_eq!(set(98), "-");
+        assert_eq!(set(103), "103");
+
+        // extreme values are written even if change is small
+        assert_eq!(set(2), "2");
+        assert_eq!(set(0), "0");
+        assert_eq!(set(1), "-");
+        assert_eq!(set(253), "253");
+        assert_eq!(set(255), "255");
+        assert_eq!(set(255), "-");
+
+        thread::sleep(Duration::from_millis(250));
+        assert_eq!(set(255), "255");
+        assert_eq!(set(254), "-");
+
+        drop(fan);
+        let enable = fs::read_to_string(hwmon.join("pwm1_enable")).unwrap();
+        assert_eq!(enable.trim(), "2");
+    }
+}
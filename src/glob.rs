@@ -0,0 +1,134 @@
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// find paths matching `pattern`
+///
+/// supports `*`, `?` and `[...]` inside path components (e.g. `/sys/class/hwmon/hwmon*/pwm[1-3]`)
+pub fn glob(pattern: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![PathBuf::new()];
+
+    for component in pattern.as_ref().components() {
+        let Component::Normal(component) = component else {
+            for path in paths.iter_mut() {
+                path.push(component);
+            }
+            continue;
+        };
+
+        let component = component.to_string_lossy();
+        if !component.contains(['*', '?', '[']) {
+            for path in paths.iter_mut() {
+                path.push(component.as_ref());
+            }
+            continue;
+        }
+
+        let mut next = Vec::new();
+        for path in paths {
+            let dir = if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path.as_path()
+            };
+
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) if err.kind() == io::ErrorKind::NotADirectory => continue,
+                Err(err) => return Err(err),
+            };
+
+            for entry in entries {
+                let name = entry?.file_name();
+                if matches(&component, &name.to_string_lossy()) {
+                    next.push(path.join(name));
+                }
+            }
+        }
+        paths = next;
+    }
+
+    let mut paths: Vec<_> = paths.into_iter().filter(|path| path.exists()).collect();
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// check that `name` matches `pattern` with `*`, `?` and `[...]` wildcards
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    matches_chars(&pattern, &name)
+}
+
+fn matches_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| matches_chars(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && matches_chars(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(end) = pattern.iter().skip(2).position(|&c| c == ']') else {
+                return name.first() == Some(&'[') && matches_chars(&pattern[1..], &name[1..]);
+            };
+            let class = &pattern[1..end + 2];
+            let Some(&c) = name.first() else {
+                return false;
+            };
+
+            matches_class(class, c) && matches_chars(&pattern[end + 3..], &name[1..])
+        }
+        Some(&p) => name.first() == Some(&p) && matches_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+fn matches_class(class: &[char], c: char) -> bool {
+    let (negate, class) = match class.first() {
+        Some('!') | Some('^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= (class[i]..=class[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+
+    found != negate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("hwmon*", "hwmon3"));
+        assert!(matches("hwmon*", "hwmon"));
+        assert!(!matches("hwmon*", "xhwmon3"));
+        assert!(matches("pwm?", "pwm1"));
+        assert!(!matches("pwm?", "pwm1_enable"));
+        assert!(matches("*_input", "temp1_input"));
+        assert!(matches("pwm", "pwm"));
+        assert!(!matches("pwm", "pwm1"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("pwm[1-3]", "pwm2"));
+        assert!(!matches("pwm[1-3]", "pwm4"));
+        assert!(matches("pwm[135]", "pwm5"));
+        assert!(!matches("pwm[!1]", "pwm1"));
+        assert!(matches("pwm[!1]", "pwm2"));
+        assert!(matches("pwm[]]", "pwm]"));
+        assert!(!matches("pwm[1-3]", "pwm"));
+    }
+}
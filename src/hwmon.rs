@@ -0,0 +1,116 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// `hwmonN` directory of sysfs `device`. it's placed in `hwmon` subdirectory for bus devices and
+/// directly in device for class devices (e.g. nvme controllers)
+pub fn find(device: &Path) -> io::Result<Option<PathBuf>> {
+    for dir in [device.join("hwmon"), device.to_owned()] {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        let mut found = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let index = name
+                .to_string_lossy()
+                .strip_prefix("hwmon")
+                .map(str::parse::<u32>);
+            if let Some(Ok(index)) = index {
+                found.push((index, entry.path()));
+            }
+        }
+        found.sort();
+
+        if let Some((_, hwmon)) = found.into_iter().next() {
+            return Ok(Some(hwmon));
+        }
+    }
+
+    Ok(None)
+}
+
+/// `tempN_input` files of `hwmon` with their labels, ordered by `N`. label is `tempN` if
+/// `tempN_label` is missing
+pub fn temperatures(hwmon: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut found = Vec::new();
+
+    for entry in fs::read_dir(hwmon)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        let Some(index) = name
+            .strip_prefix("temp")
+            .and_then(|name| name.strip_suffix("_input"))
+            .and_then(|index| index.parse::<u32>().ok())
+        else {
+            continue;
+        };
+
+        let label = match fs::read_to_string(hwmon.join(format!("temp{index}_label"))) {
+            Ok(label) => label.trim().to_string(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => format!("temp{index}"),
+            Err(err) => return Err(err),
+        };
+
+        found.push((index, label, hwmon.join(name.as_ref())));
+    }
+    found.sort_by_key(|&(index, ..)| index);
+
+    Ok(found
+        .into_iter()
+        .map(|(_, label, path)| (label, path))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, temperatures};
+    use crate::test_dir::TestDir;
+    use std::path::Path;
+
+    #[test]
+    fn hwmon() {
+        let root = TestDir::new("hwmon");
+        let hwmon = root.write(
+            "device/hwmon/hwmon4",
+            &[
+                ("temp1_input", "45000\n"),
+                ("temp1_label", "Composite\n"),
+                ("temp10_input", "50000\n"),
+                ("temp2_input", "40000\n"),
+                ("temp2_label", "Sensor 1\n"),
+                ("temp2_max", "80000\n"),
+                ("pwm1", "128\n"),
+            ],
+        );
+        root.write("device/hwmon/hwmon7", &[]);
+        root.write("nvme0/hwmon2", &[]);
+        root.write("nvme0/hwmonitor", &[]);
+
+        assert_eq!(find(&root.join("device")).unwrap(), Some(hwmon.clone()));
+        assert_eq!(
+            find(&root.join("nvme0")).unwrap(),
+            Some(root.join("nvme0/hwmon2"))
+        );
+        assert_eq!(find(&root).unwrap(), None);
+
+        let labels: Vec<_> = temperatures(&hwmon)
+            .unwrap()
+            .into_iter()
+            .map(|(label, path)| (label, path.strip_prefix(&hwmon).unwrap().to_owned()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("Composite".to_string(), Path::new("temp1_input").to_owned()),
+                ("Sensor 1".to_string(), Path::new("temp2_input").to_owned()),
+                ("temp10".to_string(), Path::new("temp10_input").to_owned()),
+            ]
+        );
+    }
+}
@@ -2,37 +2,89 @@
 extern crate dlopen_derive;
 
 use crate::{
-    config::{Config, ConfigFanTarget, ConfigSourceValue},
-    fan::{Fan, FanPower, FanPwm},
-    source::{Source, SourceFile, SourceNvidia},
+    amdgpu::{AmdgpuCard, AmdgpuSelector},
+    config::{
+        Config, ConfigAmdgpuMetric, ConfigDriveAggregate, ConfigFanMember, ConfigFanTarget,
+        ConfigNvidiaMetric, ConfigPwmMode, ConfigSourceValue,
+    },
+    fan::{Fan, FanGroup, FanGroupMember, FanNvidia, FanPower, FanPwm, FanPwmOptions, PwmMode},
+    nvidia::{Nvidia, NvidiaSelector},
+    push::PushValues,
+    source::{
+        AmdgpuMetric, CommandParser, DriveAggregate, DriveSelector, NvidiaMetric, Source,
+        SourceAmdgpu, SourceCommand, SourceDrive, SourceFile, SourceNvidia, SourcePush,
+        SourceThermalZone,
+    },
 };
 use clap::Parser as _;
 use computed::ComputeEngine;
-use config::{ConfigFan, ConfigMain};
-use std::{cell::RefCell, collections::HashMap, env, path::PathBuf, rc::Rc, str::FromStr as _};
+use config::{ConfigFan, ConfigMain, ConfigShared};
+use deno_core::{anyhow::Context as _, error::AnyError as DenoError};
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    env,
+    path::PathBuf,
+    rc::Rc,
+    str::FromStr as _,
+};
 
+mod amdgpu;
 mod cli;
 mod computed;
 mod config;
 mod fan;
+mod glob;
+mod hwmon;
+mod nvidia;
+mod push;
 mod signal_handler;
 mod source;
+mod systemd;
+#[cfg(test)]
+mod test_dir;
+mod test_formula;
 
 fn main() {
     if env::var("RUST_LOG").is_err() {
         env::set_var("RUST_LOG", "info")
     }
     env_logger::init();
-    signal_handler::init();
+    let terminate = signal_handler::init();
 
     let app = cli::App::parse();
     let path = PathBuf::from_str(app.config.as_str()).unwrap();
+    let config = Config::read_file(path).unwrap();
+
+    if let Some(cli::Command::TestFormula { cases, tolerance }) = app.command {
+        let passed = test_formula::run(&config, &cases, tolerance).unwrap_or_else(|err| {
+            log::error!("cannot read cases from {cases:?}: {err}");
+            panic!("cannot read cases from {cases:?}: {err}")
+        });
+        std::process::exit(if passed { 0 } else { 1 });
+    }
 
     let Config {
         sources,
         fans,
-        main: ConfigMain { interval },
-    } = Config::read_file(path).unwrap();
+        shared,
+        main:
+            ConfigMain {
+                interval,
+                scripts,
+                history,
+                nvidia_library,
+                push_socket,
+                push_http,
+            },
+    } = config;
+
+    // NVML is loaded only if it's used
+    // shutdown after last nvidia source and fan are dropped
+    let nvidia: OnceCell<Rc<Nvidia>> = OnceCell::new();
+
+    // values of push sources are set by listeners
+    let push = PushValues::default();
 
     let sources: HashMap<String, Rc<dyn Source>> = sources
         .into_iter()
@@ -42,9 +94,109 @@ fn main() {
                     SourceFile::new(&path, factor)
                         .expect(&format!("cant use {path:?} as source file")),
                 ),
-                ConfigSourceValue::Nvidia { name, uuid } => Rc::new(
-                    SourceNvidia::new(name, uuid).expect(&format!("cant use nvidia device")),
+                ConfigSourceValue::Nvidia {
+                    name,
+                    uuid,
+                    pci_bus_id,
+                    index,
+                    metric,
+                } => {
+                    let metric = match metric {
+                        ConfigNvidiaMetric::Temperature => NvidiaMetric::Temperature,
+                        ConfigNvidiaMetric::MemoryTemperature => NvidiaMetric::MemoryTemperature,
+                        ConfigNvidiaMetric::PowerWatts => NvidiaMetric::PowerWatts,
+                        ConfigNvidiaMetric::GpuUtilization => NvidiaMetric::GpuUtilization,
+                        ConfigNvidiaMetric::MemoryUtilization => NvidiaMetric::MemoryUtilization,
+                        ConfigNvidiaMetric::FanSpeedPercent => NvidiaMetric::FanSpeedPercent,
+                        ConfigNvidiaMetric::ClockSm => NvidiaMetric::ClockSm,
+                    };
+                    let nvidia =
+                        nvidia.get_or_init(|| Rc::new(Nvidia::library(nvidia_library.clone())));
+                    let dev = nvidia.device(NvidiaSelector {
+                        name,
+                        uuid,
+                        pci_bus_id,
+                        index,
+                    });
+                    dev.resolve()
+                        .unwrap_or_else(|err| panic!("cant use {dev}: {err}"));
+                    Rc::new(SourceNvidia::new(dev, metric))
+                }
+                ConfigSourceValue::Amdgpu {
+                    pci_bus_id,
+                    index,
+                    metric,
+                } => {
+                    let metric = match metric {
+                        ConfigAmdgpuMetric::Edge => AmdgpuMetric::Edge,
+                        ConfigAmdgpuMetric::Junction => AmdgpuMetric::Junction,
+                        ConfigAmdgpuMetric::Memory => AmdgpuMetric::Memory,
+                        ConfigAmdgpuMetric::PowerWatts => AmdgpuMetric::PowerWatts,
+                        ConfigAmdgpuMetric::GpuBusyPercent => AmdgpuMetric::GpuBusyPercent,
+                    };
+                    let selector = AmdgpuSelector { pci_bus_id, index };
+                    let source = AmdgpuCard::find(&selector)
+                        .and_then(|card| SourceAmdgpu::new(&card, metric))
+                        .unwrap_or_else(|err| panic!("cant use amdgpu card {selector}: {err}"));
+                    Rc::new(source)
+                }
+                ConfigSourceValue::Drive {
+                    id,
+                    serial,
+                    aggregate,
+                } => {
+                    let selector = match (id, serial) {
+                        (Some(id), None) => DriveSelector::Id(id),
+                        (None, Some(serial)) => DriveSelector::Serial(serial),
+                        _ => panic!("drive source {name} needs either `id` or `serial`"),
+                    };
+                    let aggregate = match aggregate {
+                        ConfigDriveAggregate::Composite => DriveAggregate::Composite,
+                        ConfigDriveAggregate::Max => DriveAggregate::Max,
+                        ConfigDriveAggregate::Average => DriveAggregate::Average,
+                    };
+                    let source = SourceDrive::new(&selector, aggregate)
+                        .unwrap_or_else(|err| panic!("cant use drive {selector:?}: {err}"));
+                    Rc::new(source)
+                }
+                ConfigSourceValue::ThermalZone { zone, trip } => Rc::new(
+                    SourceThermalZone::new(&zone, trip.as_deref())
+                        .unwrap_or_else(|err| panic!("cant use thermal zone {zone:?}: {err}")),
                 ),
+                ConfigSourceValue::Command {
+                    command,
+                    regex,
+                    json_pointer,
+                    timeout,
+                    min_interval,
+                } => {
+                    if command.is_empty() {
+                        panic!("command source {name} has empty `command`");
+                    }
+                    let parser = match (regex, json_pointer) {
+                        (None, None) => CommandParser::Plain,
+                        (Some(regex), None) => CommandParser::Regex(
+                            regex::Regex::new(&regex)
+                                .unwrap_or_else(|err| panic!("cant use regex {regex:?}: {err}")),
+                        ),
+                        (None, Some(pointer)) => CommandParser::JsonPointer(pointer),
+                        (Some(_), Some(_)) => {
+                            panic!(
+                                "command source {name} needs only one of `regex` or `json_pointer`"
+                            )
+                        }
+                    };
+                    Rc::new(SourceCommand::new(
+                        command,
+                        parser,
+                        timeout,
+                        min_interval.max(interval),
+                    ))
+                }
+                ConfigSourceValue::Push { max_age } => {
+                    push.register(&name);
+                    Rc::new(SourcePush::new(push.clone(), name.clone(), max_age))
+                }
             };
             (name, source)
         })
@@ -55,19 +207,175 @@ fn main() {
         panic!("no sources");
     }
 
-    let engine = ComputeEngine::new(sources);
+    if !push.is_empty() && push_socket.is_none() && push_http.is_none() {
+        panic!("push sources need `push_socket` or `push_http` in `main` section");
+    }
+    // socket file is removed on exit
+    let _push_socket = push_socket.map(|path| {
+        push::listen_unix(&path, push.clone())
+            .unwrap_or_else(|err| panic!("cant listen on {path:?}: {err}"))
+    });
+    if let Some(addr) = push_http {
+        if !addr.ip().is_loopback() {
+            panic!("push_http {addr} is not loopback address");
+        }
+        push::listen_http(addr, push.clone())
+            .unwrap_or_else(|err| panic!("cant listen on {addr}: {err}"));
+    }
+
+    let source_count = sources.len();
+    let engine = create_engine(sources, history, &scripts, shared.as_ref()).unwrap_or_else(|err| {
+        log::error!("{err:?}");
+        panic!("{err:?}")
+    });
 
     let mut fans: Vec<_> = fans
         .into_iter()
-        .map(|fan| {
-            let ConfigFan { value, target } = fan;
+        .enumerate()
+        .map(|(index, fan)| {
+            let name = fan.display_name(index);
+            let ConfigFan {
+                name: _,
+                value,
+                timeout,
+                failsafe,
+                target,
+            } = fan;
             let target: Rc<RefCell<dyn Fan>> = match target {
-                ConfigFanTarget::Pwm { path } => Rc::new(RefCell::new(
-                    FanPwm::new(&path).expect(&format!("cant use {path:?} as fan pwm")),
-                )),
+                ConfigFanTarget::Pwm {
+                    path,
+                    paths,
+                    glob,
+                    mode,
+                    enable_value,
+                    enable_check,
+                    min_delta,
+                    refresh,
+                } => {
+                    let options = FanPwmOptions {
+                        enable_value,
+                        mode: mode.map(|mode| match mode {
+                            ConfigPwmMode::Dc => PwmMode::Dc,
+                            ConfigPwmMode::Pwm => PwmMode::Pwm,
+                        }),
+                        enable_check,
+                        min_delta,
+                        refresh,
+                    };
+
+                    let mut members: Vec<_> = path.into_iter().map(ConfigFanMember::Path).collect();
+                    members.extend(paths);
+                    if let Some(glob) = glob {
+                        let found = glob::glob(&glob)
+                            .unwrap_or_else(|err| panic!("cant expand {glob:?}: {err}"));
+                        if found.is_empty() {
+                            log::warn!("no pwm files match {glob:?}");
+                        }
+                        members.extend(found.into_iter().map(ConfigFanMember::Path));
+                    }
+
+                    let pwm = |path: &PathBuf| {
+                        FanPwm::new(path, options.clone())
+                            .unwrap_or_else(|err| panic!("cant use {path:?} as fan pwm: {err}"))
+                    };
+
+                    for member in &members {
+                        if let ConfigFanMember::Options {
+                            path,
+                            offset,
+                            min,
+                            max,
+                        } = member
+                        {
+                            FanGroupMember::check(*offset, *min, *max).unwrap_or_else(|err| {
+                                panic!("cant use {path:?} in fan {name}: {err}")
+                            });
+                        }
+                    }
+
+                    match members.as_slice() {
+                        [] => panic!("no pwm files for fan"),
+                        [ConfigFanMember::Path(path)] => Rc::new(RefCell::new(pwm(path))),
+                        members => {
+                            let members = members
+                                .iter()
+                                .map(|member| match member {
+                                    ConfigFanMember::Path(path) => FanGroupMember {
+                                        fan: Box::new(pwm(path)),
+                                        offset: 0.0,
+                                        min: 0.0,
+                                        max: 1.0,
+                                    },
+                                    ConfigFanMember::Options {
+                                        path,
+                                        offset,
+                                        min,
+                                        max,
+                                    } => FanGroupMember {
+                                        fan: Box::new(pwm(path)),
+                                        offset: *offset,
+                                        min: *min,
+                                        max: *max,
+                                    },
+                                })
+                                .collect();
+                            Rc::new(RefCell::new(FanGroup::new(members)))
+                        }
+                    }
+                }
+                ConfigFanTarget::Nvidia {
+                    device,
+                    uuid,
+                    pci_bus_id,
+                    index,
+                    fans,
+                    refresh,
+                } => {
+                    let nvidia =
+                        nvidia.get_or_init(|| Rc::new(Nvidia::library(nvidia_library.clone())));
+                    let dev = nvidia.device(NvidiaSelector {
+                        name: device,
+                        uuid,
+                        pci_bus_id,
+                        index,
+                    });
+                    dev.resolve()
+                        .unwrap_or_else(|err| panic!("cant use {dev}: {err}"));
+                    Rc::new(RefCell::new(FanNvidia::new(dev, fans, refresh)))
+                }
+                ConfigFanTarget::Amdgpu {
+                    pci_bus_id,
+                    index,
+                    enable_check,
+                    min_delta,
+                    refresh,
+                } => {
+                    let options = FanPwmOptions {
+                        enable_value: 1,
+                        mode: None,
+                        enable_check,
+                        min_delta,
+                        refresh,
+                    };
+                    let selector = AmdgpuSelector { pci_bus_id, index };
+                    let fan = AmdgpuCard::find(&selector)
+                        .and_then(|card| card.pwm())
+                        .map_err(|err| err.to_string())
+                        .and_then(|pwm| FanPwm::new(pwm, options).map_err(|err| err.to_string()))
+                        .unwrap_or_else(|err| {
+                            panic!("cant use amdgpu card {selector} as fan: {err}")
+                        });
+                    Rc::new(RefCell::new(fan))
+                }
             };
-            let value = engine.create_computed(&value);
-            (value, target)
+            let value = engine
+                .create_computed(index, &name, &value, timeout)
+                .unwrap_or_else(|err| {
+                    log::error!("{err}");
+                    panic!("{err}")
+                });
+            let failsafe = FanPower::from((failsafe.clamp(0.0, 1.0) * 255.0) as u8);
+            (value, target, failsafe)
         })
         .collect();
 
@@ -76,18 +384,88 @@ fn main() {
         panic!("no fans");
     }
 
-    loop {
-        for (comp, fan) in fans.iter_mut() {
-            let power = comp.try_compute().unwrap_or_else(|err| {
-                log::error!("error while computing: {err:?}");
-                FanPower::full_speed()
+    let watchdog = systemd::watchdog_timeout();
+    if let Some(watchdog) = watchdog {
+        if interval >= watchdog {
+            log::warn!(
+                "interval {interval:?} is not less than systemd watchdog timeout {watchdog:?}"
+            );
+        }
+    }
+
+    let status = format!(
+        "controlling {} fans using {} sources",
+        fans.len(),
+        source_count
+    );
+    systemd::try_notify(&format!("READY=1\nSTATUS={status}"));
+    let mut last_status = status.clone();
+
+    while !terminate.is_set() {
+        let mut tick_ok = true;
+        for (comp, fan, failsafe) in fans.iter_mut() {
+            let result = comp.try_compute();
+            let name = comp.name();
+            let power = result.unwrap_or_else(|err| {
+                let failsafe = comp.failsafe(*failsafe);
+                log::error!("{name}: error while computing: {err:?}. Set failsafe {failsafe}");
+                tick_ok = false;
+                failsafe
             });
 
-            if let Err(err) = fan.as_ref().borrow_mut().try_set_power(power) {
-                log::error!("error while setting fan speed: {err:?}");
+            match fan.as_ref().borrow_mut().try_set_power(power) {
+                Ok(()) => comp.applied(power),
+                Err(err) => {
+                    log::error!("{name}: error while setting fan speed: {err:?}");
+                    tick_ok = false;
+                }
             }
         }
-        std::thread::sleep(interval);
+
+        let reasons: Vec<_> = fans
+            .iter()
+            .filter_map(|(comp, _, _)| Some(format!("{}: {}", comp.name(), comp.reason()?)))
+            .collect();
+        let current_status = if reasons.is_empty() {
+            status.clone()
+        } else {
+            format!("{status}; {}", reasons.join(", "))
+        };
+        if current_status != last_status {
+            systemd::try_notify(&format!("STATUS={current_status}"));
+            last_status = current_status;
+        }
+
+        if tick_ok && watchdog.is_some() {
+            systemd::try_notify("WATCHDOG=1");
+        }
+
+        terminate.sleep(interval);
         engine.cache_invalidate();
     }
+
+    log::info!("Termination signal received, stopping");
+    systemd::try_notify("STOPPING=1");
+}
+
+/// create engine with helpers from `scripts` and `shared` section
+fn create_engine(
+    sources: HashMap<String, Rc<dyn Source>>,
+    history: usize,
+    scripts: &[PathBuf],
+    shared: Option<&ConfigShared>,
+) -> Result<ComputeEngine, DenoError> {
+    let engine = ComputeEngine::new(sources, history);
+
+    for script in scripts {
+        engine.load_file(script)?;
+    }
+
+    if let Some(ConfigShared { script }) = shared {
+        engine
+            .load_shared(script)
+            .context("cannot load shared script")?;
+    }
+
+    Ok(engine)
 }
@@ -0,0 +1,834 @@
+use std::{
+    cell::{Cell, RefCell},
+    error::Error,
+    fmt,
+    num::NonZeroI32,
+    ptr,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+mod library;
+#[cfg(test)]
+pub mod mock;
+
+/// `NVML_TEMPERATURE_GPU`
+const TEMPERATURE_GPU: i32 = 0;
+/// `NVML_CLOCK_SM`
+const CLOCK_SM: i32 = 1;
+/// `NVML_FI_DEV_MEMORY_TEMP`
+const FIELD_MEMORY_TEMP: u32 = 82;
+
+/// `NVML_ERROR_UNINITIALIZED`
+const ERROR_UNINITIALIZED: i32 = 1;
+/// `NVML_ERROR_INVALID_ARGUMENT`
+const ERROR_INVALID_ARGUMENT: i32 = 2;
+/// `NVML_ERROR_NOT_SUPPORTED`
+const ERROR_NOT_SUPPORTED: i32 = 3;
+/// `NVML_ERROR_NOT_FOUND`
+const ERROR_NOT_FOUND: i32 = 6;
+/// `NVML_ERROR_GPU_IS_LOST`
+const ERROR_GPU_IS_LOST: i32 = 15;
+
+/// library loaded if path is not configured
+const LIBRARY: &str = "libnvidia-ml.so";
+/// library tried if configured one cannot be loaded
+const LIBRARY_FALLBACK: &str = "libnvidia-ml.so.1";
+
+/// delay before retrying failed init. doubled after every failure
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct NvidiaDeviceHandle(ptr::NonNull<()>);
+
+/// `nvmlUtilization_t`. percents of time during which gpu and memory were busy
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct NvidiaUtilization {
+    pub gpu: u32,
+    pub memory: u32,
+}
+
+/// functions of NVML used by `fand`
+pub trait Nvml {
+    fn init(&self) -> Result<(), NvidiaError>;
+    fn shutdown(&self) -> Result<(), NvidiaError>;
+    fn device_count(&self) -> Result<u32, NvidiaError>;
+    fn device_by_index(&self, index: u32) -> Result<NvidiaDeviceHandle, NvidiaError>;
+    /// `bus_id` in format `domain:bus:device.function`
+    fn device_by_pci_bus_id(&self, bus_id: &str) -> Result<NvidiaDeviceHandle, NvidiaError>;
+    fn device_name(&self, dev: NvidiaDeviceHandle) -> Result<String, NvidiaError>;
+    fn device_uuid(&self, dev: NvidiaDeviceHandle) -> Result<String, NvidiaError>;
+    /// celsius
+    fn device_temperature(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError>;
+    fn device_field_value(
+        &self,
+        dev: NvidiaDeviceHandle,
+        field_id: u32,
+    ) -> Result<f64, NvidiaError>;
+    /// milliwatts
+    fn device_power_usage(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError>;
+    fn device_utilization(&self, dev: NvidiaDeviceHandle)
+        -> Result<NvidiaUtilization, NvidiaError>;
+    /// percents of max speed of first fan
+    fn device_fan_speed(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError>;
+    /// MHz
+    fn device_clock_sm(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError>;
+    fn device_fan_count(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError>;
+    /// allowed range of fan speed in percents
+    fn device_min_max_fan_speed(&self, dev: NvidiaDeviceHandle) -> Result<(u32, u32), NvidiaError>;
+    fn device_set_fan_speed(
+        &self,
+        dev: NvidiaDeviceHandle,
+        fan: u32,
+        speed: u32,
+    ) -> Result<(), NvidiaError>;
+    fn device_set_default_fan_speed(
+        &self,
+        dev: NvidiaDeviceHandle,
+        fan: u32,
+    ) -> Result<(), NvidiaError>;
+    /// message of `error` from `nvmlErrorString`
+    fn error_string(&self, error: NvidiaError) -> String;
+}
+
+/// creates NVML api. called with backoff until it succeeds
+pub type NvidiaLoader = Box<dyn Fn() -> Result<Box<dyn Nvml>, SourceNvidiaError>>;
+
+/// NVML which is initialised on first use and reinitialised after losing gpu
+pub struct Nvidia {
+    loader: NvidiaLoader,
+    state: RefCell<NvidiaState>,
+}
+
+#[derive(Default)]
+struct NvidiaState {
+    api: Option<Box<dyn Nvml>>,
+    /// devices of initialised NVML. `None` if NVML is not initialised
+    devices: Option<Vec<NvidiaDeviceHandle>>,
+    /// incremented on every init. handles of previous inits are invalid
+    generation: u64,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+    error: Option<SourceNvidiaError>,
+}
+
+/// properties of device which must match. first device is used if all are `None`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NvidiaSelector {
+    pub name: Option<String>,
+    pub uuid: Option<String>,
+    pub pci_bus_id: Option<String>,
+    /// index of device in NVML
+    pub index: Option<u32>,
+}
+
+/// device of [`Nvidia`]. it's found by uuid again after NVML is reinitialised
+pub struct NvidiaDevice {
+    /// NVML is shutdown after last device is dropped
+    nvidia: Rc<Nvidia>,
+    selector: NvidiaSelector,
+    /// uuid of found device
+    uuid: RefCell<Option<String>>,
+    /// handle and generation of NVML it belongs to
+    handle: Cell<Option<(u64, NvidiaDeviceHandle)>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct NvidiaError(NonZeroI32);
+
+#[derive(Clone, Debug, Error)]
+pub enum SourceNvidiaError {
+    #[error("cannot load NVML: {0}")]
+    Load(String),
+    #[error("no devices")]
+    NoDevices,
+    #[error("not found {0}")]
+    NotFound(NvidiaSelector),
+    #[error(
+        "{selector} matches several devices ({}), select one by `uuid`, `pci_bus_id` or `index`",
+        .uuids.join(", ")
+    )]
+    Ambiguous {
+        selector: NvidiaSelector,
+        uuids: Vec<String>,
+    },
+    #[error("fan {fan} is not found, card has {count} fans")]
+    FanNotFound { fan: u32, count: u32 },
+    /// NVML error with its message from [`Nvml::error_string`]
+    #[error("{1}")]
+    Error(NvidiaError, String),
+}
+
+impl Nvidia {
+    pub fn new(loader: NvidiaLoader) -> Self {
+        Self {
+            loader,
+            state: RefCell::default(),
+        }
+    }
+
+    /// NVML of system loaded from `path` (`libnvidia-ml.so` by default)
+    pub fn library(path: Option<String>) -> Self {
+        Self::new(Box::new(move || {
+            let path = path.as_deref().unwrap_or(LIBRARY);
+            let api = library::load(path)
+                .or_else(|err| {
+                    log::warn!("cannot load {path}: {err}, trying {LIBRARY_FALLBACK}");
+                    library::load(LIBRARY_FALLBACK)
+                })
+                .map_err(|err| SourceNvidiaError::Load(err.to_string()))?;
+
+            Ok(Box::new(api) as Box<dyn Nvml>)
+        }))
+    }
+
+    /// device matching `selector`. it's searched on first use
+    pub fn device(self: &Rc<Self>, selector: NvidiaSelector) -> NvidiaDevice {
+        NvidiaDevice {
+            nvidia: self.clone(),
+            uuid: RefCell::new(None),
+            selector,
+            handle: Cell::new(None),
+        }
+    }
+
+    /// init NVML if it's not initialised and backoff is passed. returns generation of NVML
+    fn connect(&self, state: &mut NvidiaState, now: Instant) -> Result<u64, SourceNvidiaError> {
+        if state.devices.is_some() {
+            return Ok(state.generation);
+        }
+
+        if let (Some(retry_at), Some(error)) = (state.retry_at, &state.error) {
+            if now < retry_at {
+                return Err(error.clone());
+            }
+        }
+
+        match self.init(&mut state.api) {
+            Ok(devices) => {
+                state.devices = Some(devices);
+                state.generation += 1;
+                state.backoff = Duration::ZERO;
+                state.retry_at = None;
+                state.error = None;
+
+                Ok(state.generation)
+            }
+            Err(err) => {
+                state.backoff = (state.backoff * 2).clamp(BACKOFF_MIN, BACKOFF_MAX);
+                log::warn!("cannot init NVML: {err}, retrying in {:?}", state.backoff);
+                state.retry_at = Some(now + state.backoff);
+                state.error = Some(err.clone());
+
+                Err(err)
+            }
+        }
+    }
+
+    /// load and init NVML, returns its devices
+    fn init(
+        &self,
+        api: &mut Option<Box<dyn Nvml>>,
+    ) -> Result<Vec<NvidiaDeviceHandle>, SourceNvidiaError> {
+        if api.is_none() {
+            *api = Some((self.loader)()?);
+        }
+        let api = api.as_deref().unwrap();
+
+        api.init().map_err(|err| Self::describe(api, err.into()))?;
+
+        let devices = Self::enumerate(api);
+        if devices.is_err() {
+            let _ = api.shutdown();
+        }
+
+        devices.map_err(|err| Self::describe(api, err.into()))
+    }
+
+    /// replace message of NVML error by message from the library
+    fn describe(api: &dyn Nvml, err: SourceNvidiaError) -> SourceNvidiaError {
+        match err {
+            SourceNvidiaError::Error(error, _) => {
+                SourceNvidiaError::Error(error, api.error_string(error))
+            }
+            err => err,
+        }
+    }
+
+    fn enumerate(api: &dyn Nvml) -> Result<Vec<NvidiaDeviceHandle>, NvidiaError> {
+        let mut devices = Vec::new();
+
+        for index in 0..api.device_count()? {
+            let handle = api.device_by_index(index)?;
+
+            log::info!(
+                "Found NvidiaDevice {{ name: {:?}, uuid: {:?} }}",
+                api.device_name(handle)
+                    .unwrap_or_else(|_| "<ERROR>".to_string()),
+                api.device_uuid(handle)
+                    .unwrap_or_else(|_| "<ERROR>".to_string()),
+            );
+
+            devices.push(handle);
+        }
+
+        Ok(devices)
+    }
+
+    /// shutdown NVML. it's initialised again on next call without waiting for backoff
+    fn reset(state: &mut NvidiaState) {
+        if let (Some(api), Some(_)) = (&state.api, state.devices.take()) {
+            if let Err(err) = api.shutdown() {
+                log::error!("cannot deinit nvidia api: {err:?}");
+            }
+        }
+        state.retry_at = None;
+    }
+
+    fn find(
+        api: &dyn Nvml,
+        devices: &[NvidiaDeviceHandle],
+        selector: &NvidiaSelector,
+    ) -> Result<NvidiaDeviceHandle, SourceNvidiaError> {
+        if devices.is_empty() {
+            return Err(SourceNvidiaError::NoDevices);
+        }
+
+        let not_found = || SourceNvidiaError::NotFound(selector.clone());
+
+        let pci = match &selector.pci_bus_id {
+            Some(bus_id) => match api.device_by_pci_bus_id(bus_id) {
+                Ok(handle) => Some(handle),
+                Err(err) if err.is_not_found() => return Err(not_found()),
+                Err(err) => return Err(err.into()),
+            },
+            None => None,
+        };
+
+        let mut found = Vec::new();
+        for (index, &handle) in devices.iter().enumerate() {
+            if selector.index.is_some_and(|i| i as usize != index) {
+                continue;
+            }
+            if pci.is_some_and(|pci| pci != handle) {
+                continue;
+            }
+            if let Some(name) = &selector.name {
+                if api.device_name(handle)? != *name {
+                    continue;
+                }
+            }
+            if let Some(uuid) = &selector.uuid {
+                if api.device_uuid(handle)? != *uuid {
+                    continue;
+                }
+            }
+
+            found.push(handle);
+        }
+
+        match found[..] {
+            [] => Err(not_found()),
+            [handle] => Ok(handle),
+            [handle, ..] if *selector == NvidiaSelector::default() => Ok(handle),
+            _ => Err(SourceNvidiaError::Ambiguous {
+                selector: selector.clone(),
+                uuids: found
+                    .iter()
+                    .map(|&handle| api.device_uuid(handle))
+                    .collect::<Result<_, _>>()?,
+            }),
+        }
+    }
+
+    /// call `f` with handle of `dev`. NVML is reinitialised if it reports lost gpu
+    fn call<T>(
+        &self,
+        dev: &NvidiaDevice,
+        f: impl FnOnce(&dyn Nvml, NvidiaDeviceHandle) -> Result<T, NvidiaError>,
+    ) -> Result<T, SourceNvidiaError> {
+        let mut state = self.state.borrow_mut();
+        let generation = self.connect(&mut state, Instant::now())?;
+
+        let api = state.api.as_deref().unwrap();
+        let result = (|| {
+            let handle = match dev.handle.get() {
+                Some((handle_generation, handle)) if handle_generation == generation => handle,
+                _ => {
+                    let devices = state.devices.as_deref().unwrap_or_default();
+                    let handle = dev.find(api, devices)?;
+                    dev.handle.set(Some((generation, handle)));
+                    handle
+                }
+            };
+
+            Ok(f(api, handle)?)
+        })()
+        .map_err(|err| Self::describe(api, err));
+
+        if let Err(SourceNvidiaError::Error(err, message)) = &result {
+            if err.is_lost() {
+                log::warn!("{dev}: {message}, reinitialising NVML");
+                Self::reset(&mut state);
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for Nvidia {
+    fn drop(&mut self) {
+        Self::reset(self.state.get_mut());
+    }
+}
+
+impl NvidiaDevice {
+    /// find handle of device by uuid if it was found before, otherwise by selector
+    fn find(
+        &self,
+        api: &dyn Nvml,
+        devices: &[NvidiaDeviceHandle],
+    ) -> Result<NvidiaDeviceHandle, SourceNvidiaError> {
+        let uuid = self.uuid.borrow().clone();
+        if let Some(uuid) = uuid {
+            let selector = NvidiaSelector {
+                uuid: Some(uuid),
+                ..Default::default()
+            };
+            return Nvidia::find(api, devices, &selector).map_err(|err| match err {
+                SourceNvidiaError::NotFound(_) => {
+                    SourceNvidiaError::NotFound(self.selector.clone())
+                }
+                err => err,
+            });
+        }
+
+        let handle = Nvidia::find(api, devices, &self.selector)?;
+        let uuid = api.device_uuid(handle)?;
+        log::info!("{self} is {uuid}");
+        *self.uuid.borrow_mut() = Some(uuid);
+
+        Ok(handle)
+    }
+
+    /// find device now, so wrong selector is reported at start. errors of NVML, which could be
+    /// not ready yet, are only logged since NVML is initialised again on use
+    pub fn resolve(&self) -> Result<(), SourceNvidiaError> {
+        match self.nvidia.call(self, |_, _| Ok(())) {
+            Ok(()) => Ok(()),
+            Err(err @ (SourceNvidiaError::NotFound(_) | SourceNvidiaError::Ambiguous { .. })) => {
+                Err(err)
+            }
+            Err(err) => {
+                log::warn!("{self}: {err}, it's searched again on use");
+                Ok(())
+            }
+        }
+    }
+
+    /// generation of NVML if it's initialised. handles and fan settings of other generations
+    /// could be lost
+    pub fn generation(&self) -> Option<u64> {
+        let state = self.nvidia.state.borrow();
+        state.devices.as_ref().map(|_| state.generation)
+    }
+
+    /// celsius
+    pub fn try_get_temperature(&self) -> Result<u32, SourceNvidiaError> {
+        self.nvidia
+            .call(self, |api, dev| api.device_temperature(dev))
+    }
+
+    /// celsius
+    pub fn try_get_memory_temperature(&self) -> Result<f64, SourceNvidiaError> {
+        self.nvidia.call(self, |api, dev| {
+            api.device_field_value(dev, FIELD_MEMORY_TEMP)
+        })
+    }
+
+    /// milliwatts
+    pub fn try_get_power_usage(&self) -> Result<u32, SourceNvidiaError> {
+        self.nvidia
+            .call(self, |api, dev| api.device_power_usage(dev))
+    }
+
+    /// percents of max speed of first fan
+    pub fn try_get_fan_speed(&self) -> Result<u32, SourceNvidiaError> {
+        self.nvidia.call(self, |api, dev| api.device_fan_speed(dev))
+    }
+
+    /// MHz
+    pub fn try_get_clock_sm(&self) -> Result<u32, SourceNvidiaError> {
+        self.nvidia.call(self, |api, dev| api.device_clock_sm(dev))
+    }
+
+    pub fn try_get_fan_count(&self) -> Result<u32, SourceNvidiaError> {
+        self.nvidia.call(self, |api, dev| api.device_fan_count(dev))
+    }
+
+    /// allowed range of fan speed in percents
+    pub fn try_get_min_max_fan_speed(&self) -> Result<(u32, u32), SourceNvidiaError> {
+        self.nvidia
+            .call(self, |api, dev| api.device_min_max_fan_speed(dev))
+    }
+
+    /// switch fan to manual control with `speed` in percents
+    pub fn try_set_fan_speed(&self, fan: u32, speed: u32) -> Result<(), SourceNvidiaError> {
+        self.nvidia
+            .call(self, |api, dev| api.device_set_fan_speed(dev, fan, speed))
+    }
+
+    /// return fan to control by driver
+    pub fn try_set_default_fan_speed(&self, fan: u32) -> Result<(), SourceNvidiaError> {
+        self.nvidia
+            .call(self, |api, dev| api.device_set_default_fan_speed(dev, fan))
+    }
+
+    pub fn try_get_utilization(&self) -> Result<NvidiaUtilization, SourceNvidiaError> {
+        self.nvidia
+            .call(self, |api, dev| api.device_utilization(dev))
+    }
+}
+
+impl NvidiaError {
+    /// NVML must be reinitialised after this error
+    fn is_lost(self) -> bool {
+        matches!(self.0.get(), ERROR_UNINITIALIZED | ERROR_GPU_IS_LOST)
+    }
+
+    fn is_not_found(self) -> bool {
+        matches!(self.0.get(), ERROR_INVALID_ARGUMENT | ERROR_NOT_FOUND)
+    }
+}
+
+impl fmt::Display for NvidiaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NVML error {}", self.0)
+    }
+}
+
+impl fmt::Debug for NvidiaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for NvidiaError {}
+
+impl From<NvidiaError> for SourceNvidiaError {
+    fn from(value: NvidiaError) -> Self {
+        Self::Error(value, value.to_string())
+    }
+}
+
+impl fmt::Display for NvidiaSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        if let Some(name) = &self.name {
+            write!(f, " name = {name:?}")?;
+        }
+        if let Some(uuid) = &self.uuid {
+            write!(f, " uuid = {uuid:?}")?;
+        }
+        if let Some(pci_bus_id) = &self.pci_bus_id {
+            write!(f, " pci_bus_id = {pci_bus_id:?}")?;
+        }
+        if let Some(index) = self.index {
+            write!(f, " index = {index}")?;
+        }
+        f.write_str(" }")
+    }
+}
+
+impl fmt::Display for NvidiaDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NvidiaDevice {}", self.selector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        mock::{MockDevice, MockNvml, MockState},
+        Nvidia, NvidiaDevice, NvidiaDeviceHandle, NvidiaError, NvidiaSelector, Nvml,
+        SourceNvidiaError,
+    };
+    use std::{
+        cell::{Cell, RefCell},
+        mem::size_of,
+        num::NonZeroI32,
+        rc::Rc,
+        time::Instant,
+    };
+
+    fn nvidia(devices: Vec<MockDevice>) -> (Rc<Nvidia>, Rc<RefCell<MockState>>) {
+        let api = MockNvml::new(devices);
+        let state = api.state();
+        let nvidia = Rc::new(Nvidia::new(Box::new(move || {
+            Ok(Box::new(api.clone()) as Box<dyn Nvml>)
+        })));
+
+        (nvidia, state)
+    }
+
+    fn uuid(dev: &NvidiaDevice) -> Result<String, SourceNvidiaError> {
+        dev.nvidia.call(dev, |api, dev| api.device_uuid(dev))
+    }
+
+    fn selector(name: Option<&str>, uuid: Option<&str>) -> NvidiaSelector {
+        NvidiaSelector {
+            name: name.map(String::from),
+            uuid: uuid.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn nvidia_error_sizes() {
+        assert_eq!(size_of::<NvidiaError>(), size_of::<i32>());
+        assert_eq!(size_of::<Option<NvidiaError>>(), size_of::<NvidiaError>());
+        assert_eq!(
+            size_of::<Result<(), NvidiaError>>(),
+            size_of::<NvidiaError>()
+        );
+    }
+
+    #[test]
+    fn nvidia_device_handle_sizes() {
+        assert_eq!(size_of::<NvidiaDeviceHandle>(), size_of::<*const ()>());
+        assert_eq!(
+            size_of::<Option<NvidiaDeviceHandle>>(),
+            size_of::<NvidiaDeviceHandle>()
+        );
+    }
+
+    #[test]
+    fn error_string() {
+        let error = |code| NvidiaError(NonZeroI32::new(code).unwrap());
+
+        assert_eq!(error(3).to_string(), "NVML error 3");
+        assert_eq!(
+            SourceNvidiaError::from(error(4)).to_string(),
+            "NVML error 4"
+        );
+        assert_eq!(
+            SourceNvidiaError::Error(error(4), "Insufficient Permissions".to_string()).to_string(),
+            "Insufficient Permissions"
+        );
+        assert_eq!(
+            SourceNvidiaError::Load("libnvidia-ml.so: not found".to_string()).to_string(),
+            "cannot load NVML: libnvidia-ml.so: not found"
+        );
+    }
+
+    #[test]
+    fn find_device() {
+        let (nvidia, _) = nvidia(vec![
+            MockDevice::new("NVIDIA GeForce RTX 4090", "GPU-0"),
+            MockDevice::new("NVIDIA GeForce RTX 3060", "GPU-1"),
+            MockDevice::new("NVIDIA GeForce RTX 3060", "GPU-2"),
+        ]);
+        let uuid = |name, uuid| self::uuid(&nvidia.device(selector(name, uuid)));
+
+        assert_eq!(uuid(None, None).unwrap(), "GPU-0");
+        assert_eq!(
+            uuid(Some("NVIDIA GeForce RTX 4090"), None).unwrap(),
+            "GPU-0"
+        );
+        assert_eq!(
+            uuid(Some("NVIDIA GeForce RTX 3060"), None)
+                .unwrap_err()
+                .to_string(),
+            r#"{ name = "NVIDIA GeForce RTX 3060" } matches several devices (GPU-1, GPU-2), select one by `uuid`, `pci_bus_id` or `index`"#
+        );
+        assert_eq!(uuid(None, Some("GPU-2")).unwrap(), "GPU-2");
+        assert_eq!(
+            uuid(Some("NVIDIA GeForce RTX 3060"), Some("GPU-2")).unwrap(),
+            "GPU-2"
+        );
+        assert!(matches!(
+            uuid(Some("NVIDIA GeForce RTX 4090"), Some("GPU-2")),
+            Err(SourceNvidiaError::NotFound(_))
+        ));
+        assert_eq!(
+            uuid(None, Some("GPU-3")).unwrap_err().to_string(),
+            r#"not found { uuid = "GPU-3" }"#
+        );
+
+        let (empty, _) = self::nvidia(Vec::new());
+        assert!(matches!(
+            self::uuid(&empty.device(selector(None, None))),
+            Err(SourceNvidiaError::NoDevices)
+        ));
+    }
+
+    #[test]
+    fn find_device_by_pci_bus_id_and_index() {
+        let mut devices = vec![
+            MockDevice::new("NVIDIA GeForce RTX 3060", "GPU-0"),
+            MockDevice::new("NVIDIA GeForce RTX 3060", "GPU-1"),
+        ];
+        devices[0].pci_bus_id = "0000:01:00.0".to_string();
+        devices[1].pci_bus_id = "0000:02:00.0".to_string();
+        let (nvidia, _) = nvidia(devices);
+        let uuid = |selector| self::uuid(&nvidia.device(selector));
+
+        let pci = |bus_id: &str| NvidiaSelector {
+            pci_bus_id: Some(bus_id.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(uuid(pci("0000:02:00.0")).unwrap(), "GPU-1");
+        assert_eq!(
+            uuid(pci("0000:03:00.0")).unwrap_err().to_string(),
+            r#"not found { pci_bus_id = "0000:03:00.0" }"#
+        );
+
+        let index = |index| NvidiaSelector {
+            index: Some(index),
+            ..Default::default()
+        };
+        assert_eq!(uuid(index(1)).unwrap(), "GPU-1");
+        assert!(matches!(
+            uuid(index(2)),
+            Err(SourceNvidiaError::NotFound(_))
+        ));
+
+        // name must match too
+        let selector = NvidiaSelector {
+            name: Some("NVIDIA GeForce RTX 4090".to_string()),
+            index: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            uuid(selector),
+            Err(SourceNvidiaError::NotFound(_))
+        ));
+        let selector = NvidiaSelector {
+            name: Some("NVIDIA GeForce RTX 3060".to_string()),
+            pci_bus_id: Some("0000:01:00.0".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(uuid(selector).unwrap(), "GPU-0");
+    }
+
+    #[test]
+    fn reconnect() {
+        let mut devices = vec![
+            MockDevice::new("NVIDIA GeForce RTX 3060", "GPU-0"),
+            MockDevice::new("NVIDIA GeForce RTX 4090", "GPU-1"),
+        ];
+        devices[1].temperature = 60;
+        let (nvidia, state) = nvidia(devices);
+
+        // found by name, then by uuid of found device
+        let dev = nvidia.device(selector(Some("NVIDIA GeForce RTX 3060"), None));
+        assert_eq!(dev.try_get_temperature().unwrap(), 40);
+
+        state.borrow_mut().devices[0].lost = true;
+        assert_eq!(
+            dev.try_get_temperature().unwrap_err().to_string(),
+            "mock error 15"
+        );
+
+        // gpu is back after other one
+        {
+            let mut state = state.borrow_mut();
+            let lost = state.devices.remove(0);
+            state.devices.push(MockDevice {
+                lost: false,
+                ..lost
+            });
+        }
+        assert_eq!(dev.try_get_temperature().unwrap(), 40);
+        assert_eq!(uuid(&dev).unwrap(), "GPU-0");
+        assert_eq!(state.borrow().inits, 2);
+
+        // NVML was shutdown by someone else
+        state.borrow_mut().initialized = false;
+        assert_eq!(
+            dev.try_get_temperature().unwrap_err().to_string(),
+            "mock error 1"
+        );
+        assert_eq!(dev.try_get_temperature().unwrap(), 40);
+        assert_eq!(state.borrow().inits, 3);
+    }
+
+    #[test]
+    fn resolve() {
+        let (nvidia, state) = nvidia(vec![MockDevice::new("NVIDIA GeForce RTX 4090", "GPU-0")]);
+
+        assert!(nvidia.device(selector(None, None)).resolve().is_ok());
+        assert_eq!(
+            nvidia
+                .device(selector(None, Some("GPU-1")))
+                .resolve()
+                .unwrap_err()
+                .to_string(),
+            r#"not found { uuid = "GPU-1" }"#
+        );
+
+        // device could appear after driver is loaded
+        Nvidia::reset(&mut nvidia.state.borrow_mut());
+        state.borrow_mut().init_error = Some(9);
+        assert!(nvidia
+            .device(selector(None, Some("GPU-1")))
+            .resolve()
+            .is_ok());
+    }
+
+    #[test]
+    fn init_backoff() {
+        let (nvidia, state) = nvidia(vec![MockDevice::new("NVIDIA GeForce RTX 4090", "GPU-0")]);
+        let dev = nvidia.device(selector(None, None));
+
+        state.borrow_mut().init_error = Some(9);
+        assert_eq!(
+            dev.try_get_temperature().unwrap_err().to_string(),
+            "mock error 9"
+        );
+        // init is not retried until backoff is passed
+        state.borrow_mut().init_error = None;
+        assert_eq!(
+            dev.try_get_temperature().unwrap_err().to_string(),
+            "mock error 9"
+        );
+        assert_eq!(state.borrow().inits, 1);
+
+        nvidia.state.borrow_mut().retry_at = Some(Instant::now());
+        assert_eq!(dev.try_get_temperature().unwrap(), 40);
+        assert_eq!(state.borrow().inits, 2);
+    }
+
+    #[test]
+    fn load_error() {
+        let loads = Rc::new(Cell::new(0));
+        let nvidia = Rc::new(Nvidia::new(Box::new({
+            let loads = loads.clone();
+            move || {
+                loads.set(loads.get() + 1);
+                Err(SourceNvidiaError::Load(
+                    "libnvidia-ml.so: not found".to_string(),
+                ))
+            }
+        })));
+        let dev = nvidia.device(selector(None, None));
+
+        assert_eq!(
+            dev.try_get_temperature().unwrap_err().to_string(),
+            "cannot load NVML: libnvidia-ml.so: not found"
+        );
+        assert!(dev.try_get_temperature().is_err());
+        assert_eq!(loads.get(), 1);
+
+        let backoff = nvidia.state.borrow().backoff;
+        nvidia.state.borrow_mut().retry_at = Some(Instant::now());
+        assert!(dev.try_get_temperature().is_err());
+        assert_eq!(loads.get(), 2);
+        assert_eq!(nvidia.state.borrow().backoff, backoff * 2);
+    }
+}
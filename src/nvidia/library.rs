@@ -0,0 +1,338 @@
+use super::{
+    NvidiaDeviceHandle, NvidiaError, NvidiaUtilization, Nvml, CLOCK_SM, ERROR_INVALID_ARGUMENT,
+    ERROR_NOT_SUPPORTED, TEMPERATURE_GPU,
+};
+use dlopen::{
+    raw::Library,
+    wrapper::{Container, WrapperApi},
+};
+use std::{
+    ffi::{c_char, CStr, CString},
+    num::NonZeroI32,
+};
+
+/// `nvmlFieldValue_t`
+#[derive(Default)]
+#[repr(C)]
+struct NvidiaFieldValue {
+    field_id: u32,
+    scope_id: u32,
+    timestamp: i64,
+    latency: i64,
+    value_type: i32,
+    ret: i32,
+    /// `nvmlValue_t` union
+    value: u64,
+}
+
+#[derive(WrapperApi)]
+pub struct NvidiaApi {
+    #[dlopen_name = "nvmlInit_v2"]
+    init: fn() -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlShutdown"]
+    deinit: fn() -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlErrorString"]
+    error_string: fn(result: i32) -> *const c_char,
+
+    #[dlopen_name = "nvmlDeviceGetCount_v2"]
+    devices_count: fn(count: &mut u32) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetHandleByIndex_v2"]
+    device_handle_by_index:
+        fn(index: u32, dev: &mut Option<NvidiaDeviceHandle>) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetHandleByPciBusId_v2"]
+    device_handle_by_pci_bus_id:
+        fn(bus_id: *const c_char, dev: &mut Option<NvidiaDeviceHandle>) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetUUID"]
+    device_get_uuid:
+        fn(dev: NvidiaDeviceHandle, buf: *mut u8, size: u32) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetName"]
+    device_get_name:
+        fn(dev: NvidiaDeviceHandle, name: *mut u8, len: u32) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetTemperature"]
+    device_get_temperature:
+        fn(dev: NvidiaDeviceHandle, types: i32, temp: &mut u32) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetFieldValues"]
+    device_get_field_values: fn(
+        dev: NvidiaDeviceHandle,
+        count: i32,
+        values: *mut NvidiaFieldValue,
+    ) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetPowerUsage"]
+    device_get_power_usage:
+        fn(dev: NvidiaDeviceHandle, milliwatts: &mut u32) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetUtilizationRates"]
+    device_get_utilization_rates:
+        fn(dev: NvidiaDeviceHandle, utilization: &mut NvidiaUtilization) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetFanSpeed"]
+    device_get_fan_speed: fn(dev: NvidiaDeviceHandle, speed: &mut u32) -> Result<(), NvidiaError>,
+
+    #[dlopen_name = "nvmlDeviceGetClockInfo"]
+    device_get_clock_info:
+        fn(dev: NvidiaDeviceHandle, types: i32, clock: &mut u32) -> Result<(), NvidiaError>,
+}
+
+type GetNumFans = fn(NvidiaDeviceHandle, &mut u32) -> Result<(), NvidiaError>;
+type GetMinMaxFanSpeed = fn(NvidiaDeviceHandle, &mut u32, &mut u32) -> Result<(), NvidiaError>;
+type SetFanSpeed = fn(NvidiaDeviceHandle, u32, u32) -> Result<(), NvidiaError>;
+type SetDefaultFanSpeed = fn(NvidiaDeviceHandle, u32) -> Result<(), NvidiaError>;
+
+/// fan control functions, missing in older drivers
+struct NvidiaFanApi {
+    device_get_num_fans: Option<GetNumFans>,
+    device_get_min_max_fan_speed: Option<GetMinMaxFanSpeed>,
+    device_set_fan_speed: Option<SetFanSpeed>,
+    device_set_default_fan_speed: Option<SetDefaultFanSpeed>,
+    /// keeps functions loaded
+    _library: Library,
+}
+
+/// NVML loaded from file
+pub struct NvidiaLibrary {
+    api: Container<NvidiaApi>,
+    fans: NvidiaFanApi,
+}
+
+/// load NVML from `path`
+pub fn load(path: &str) -> Result<NvidiaLibrary, dlopen::Error> {
+    let api = unsafe { Container::load(path) }?;
+
+    let library = Library::open(path)?;
+    let fans = unsafe {
+        NvidiaFanApi {
+            device_get_num_fans: library.symbol("nvmlDeviceGetNumFans").ok(),
+            device_get_min_max_fan_speed: library.symbol("nvmlDeviceGetMinMaxFanSpeed").ok(),
+            device_set_fan_speed: library.symbol("nvmlDeviceSetFanSpeed_v2").ok(),
+            device_set_default_fan_speed: library.symbol("nvmlDeviceSetDefaultFanSpeed_v2").ok(),
+            _library: library,
+        }
+    };
+
+    Ok(NvidiaLibrary { api, fans })
+}
+
+/// function of [`NvidiaFanApi`] or `NVML_ERROR_NOT_SUPPORTED` if driver has no such function
+fn supported<F>(function: Option<F>) -> Result<F, NvidiaError> {
+    function.ok_or(NvidiaError(NonZeroI32::new(ERROR_NOT_SUPPORTED).unwrap()))
+}
+
+/// read nul terminated string written to `buf`
+fn string(buf: &[u8]) -> String {
+    let string = CStr::from_bytes_until_nul(buf).unwrap_or_default();
+    string.to_string_lossy().into_owned()
+}
+
+impl Nvml for NvidiaLibrary {
+    fn init(&self) -> Result<(), NvidiaError> {
+        self.api.init()
+    }
+
+    fn shutdown(&self) -> Result<(), NvidiaError> {
+        self.api.deinit()
+    }
+
+    fn device_count(&self) -> Result<u32, NvidiaError> {
+        let mut count = 0;
+        self.api.devices_count(&mut count)?;
+
+        Ok(count)
+    }
+
+    fn device_by_index(&self, index: u32) -> Result<NvidiaDeviceHandle, NvidiaError> {
+        let mut handle = None;
+        self.api.device_handle_by_index(index, &mut handle)?;
+
+        Ok(handle.expect("nvml returned null device handle"))
+    }
+
+    fn device_by_pci_bus_id(&self, bus_id: &str) -> Result<NvidiaDeviceHandle, NvidiaError> {
+        let bus_id = CString::new(bus_id)
+            .map_err(|_| NvidiaError(NonZeroI32::new(ERROR_INVALID_ARGUMENT).unwrap()))?;
+
+        let mut handle = None;
+        self.api
+            .device_handle_by_pci_bus_id(bus_id.as_ptr(), &mut handle)?;
+
+        Ok(handle.expect("nvml returned null device handle"))
+    }
+
+    fn device_name(&self, dev: NvidiaDeviceHandle) -> Result<String, NvidiaError> {
+        let mut buf = [0u8; 4096];
+        self.api
+            .device_get_name(dev, buf.as_mut_ptr(), buf.len() as u32)?;
+
+        Ok(string(&buf))
+    }
+
+    fn device_uuid(&self, dev: NvidiaDeviceHandle) -> Result<String, NvidiaError> {
+        let mut buf = [0u8; 96];
+        self.api
+            .device_get_uuid(dev, buf.as_mut_ptr(), buf.len() as u32)?;
+
+        Ok(string(&buf))
+    }
+
+    fn device_temperature(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        let mut temp = 0;
+        self.api
+            .device_get_temperature(dev, TEMPERATURE_GPU, &mut temp)?;
+
+        Ok(temp)
+    }
+
+    fn device_field_value(
+        &self,
+        dev: NvidiaDeviceHandle,
+        field_id: u32,
+    ) -> Result<f64, NvidiaError> {
+        let mut field = NvidiaFieldValue {
+            field_id,
+            ..Default::default()
+        };
+        self.api.device_get_field_values(dev, 1, &mut field)?;
+
+        if let Some(ret) = NonZeroI32::new(field.ret) {
+            return Err(NvidiaError(ret));
+        }
+
+        Ok(field.value())
+    }
+
+    fn device_power_usage(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        let mut power = 0;
+        self.api.device_get_power_usage(dev, &mut power)?;
+
+        Ok(power)
+    }
+
+    fn device_utilization(
+        &self,
+        dev: NvidiaDeviceHandle,
+    ) -> Result<NvidiaUtilization, NvidiaError> {
+        let mut utilization = NvidiaUtilization::default();
+        self.api
+            .device_get_utilization_rates(dev, &mut utilization)?;
+
+        Ok(utilization)
+    }
+
+    fn device_fan_speed(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        let mut speed = 0;
+        self.api.device_get_fan_speed(dev, &mut speed)?;
+
+        Ok(speed)
+    }
+
+    fn device_clock_sm(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        let mut clock = 0;
+        self.api.device_get_clock_info(dev, CLOCK_SM, &mut clock)?;
+
+        Ok(clock)
+    }
+
+    fn device_fan_count(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        let mut count = 0;
+        supported(self.fans.device_get_num_fans)?(dev, &mut count)?;
+
+        Ok(count)
+    }
+
+    fn device_min_max_fan_speed(&self, dev: NvidiaDeviceHandle) -> Result<(u32, u32), NvidiaError> {
+        let (mut min, mut max) = (0, 0);
+        supported(self.fans.device_get_min_max_fan_speed)?(dev, &mut min, &mut max)?;
+
+        Ok((min, max))
+    }
+
+    fn device_set_fan_speed(
+        &self,
+        dev: NvidiaDeviceHandle,
+        fan: u32,
+        speed: u32,
+    ) -> Result<(), NvidiaError> {
+        supported(self.fans.device_set_fan_speed)?(dev, fan, speed)
+    }
+
+    fn device_set_default_fan_speed(
+        &self,
+        dev: NvidiaDeviceHandle,
+        fan: u32,
+    ) -> Result<(), NvidiaError> {
+        supported(self.fans.device_set_default_fan_speed)?(dev, fan)
+    }
+
+    fn error_string(&self, error: NvidiaError) -> String {
+        let message = self.api.error_string(error.0.get());
+        if message.is_null() {
+            return error.to_string();
+        }
+
+        // NVML returns static nul terminated string
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl NvidiaFieldValue {
+    /// decode `nvmlValue_t` by `nvmlValueType_t`
+    fn value(&self) -> f64 {
+        match self.value_type {
+            0 => f64::from_bits(self.value),
+            1 => self.value as u32 as f64,
+            2 | 3 => self.value as f64,
+            4 => self.value as i64 as f64,
+            5 => self.value as u32 as i32 as f64,
+            6 => self.value as u16 as f64,
+            _ => f64::NAN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{string, supported, GetNumFans, NvidiaFieldValue};
+    use std::mem::size_of;
+
+    #[test]
+    fn nvidia_field_value() {
+        assert_eq!(size_of::<NvidiaFieldValue>(), 40);
+
+        let field = |value_type, value| NvidiaFieldValue {
+            value_type,
+            value,
+            ..Default::default()
+        };
+        assert_eq!(field(0, 42.5f64.to_bits()).value(), 42.5);
+        assert_eq!(field(1, 0xffff_ffff_0000_002a).value(), 42.0);
+        assert_eq!(field(3, 42).value(), 42.0);
+        assert_eq!(field(5, (-42i32) as u32 as u64).value(), -42.0);
+        assert!(field(7, 42).value().is_nan());
+    }
+
+    #[test]
+    fn missing_function() {
+        let err = supported(None::<GetNumFans>).unwrap_err();
+        assert_eq!(err.to_string(), "NVML error 3");
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            string(b"NVIDIA GeForce RTX 4090\0\0\0"),
+            "NVIDIA GeForce RTX 4090"
+        );
+        assert_eq!(string(b"\0"), "");
+        assert_eq!(string(b"no nul"), "");
+    }
+}
@@ -0,0 +1,230 @@
+use super::{
+    NvidiaDeviceHandle, NvidiaError, NvidiaUtilization, Nvml, ERROR_GPU_IS_LOST,
+    ERROR_INVALID_ARGUMENT, ERROR_NOT_FOUND, ERROR_NOT_SUPPORTED, ERROR_UNINITIALIZED,
+    FIELD_MEMORY_TEMP,
+};
+use std::{cell::RefCell, num::NonZeroI32, ptr::NonNull, rc::Rc};
+
+fn error(code: i32) -> NvidiaError {
+    NvidiaError(NonZeroI32::new(code).unwrap())
+}
+
+/// device of [`MockNvml`]
+pub struct MockDevice {
+    pub name: String,
+    pub uuid: String,
+    pub pci_bus_id: String,
+    pub temperature: u32,
+    pub memory_temperature: Option<f64>,
+    pub power: u32,
+    pub utilization: NvidiaUtilization,
+    pub clock_sm: u32,
+    pub min_max_fan_speed: (u32, u32),
+    /// `None` for fan controlled by driver
+    pub fans: Vec<Option<u32>>,
+    /// every call returns `NVML_ERROR_GPU_IS_LOST`
+    pub lost: bool,
+}
+
+#[derive(Default)]
+pub struct MockState {
+    pub devices: Vec<MockDevice>,
+    pub initialized: bool,
+    /// count of `init` calls
+    pub inits: u32,
+    /// error returned by `init`
+    pub init_error: Option<i32>,
+}
+
+/// in-memory NVML for tests
+#[derive(Clone)]
+pub struct MockNvml(Rc<RefCell<MockState>>);
+
+impl MockDevice {
+    pub fn new(name: &str, uuid: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            uuid: uuid.to_string(),
+            pci_bus_id: String::new(),
+            temperature: 40,
+            memory_temperature: None,
+            power: 100_000,
+            utilization: NvidiaUtilization::default(),
+            clock_sm: 1500,
+            min_max_fan_speed: (30, 100),
+            fans: vec![None, None],
+            lost: false,
+        }
+    }
+}
+
+impl MockNvml {
+    pub fn new(devices: Vec<MockDevice>) -> Self {
+        Self(Rc::new(RefCell::new(MockState {
+            devices,
+            ..Default::default()
+        })))
+    }
+
+    /// state shared with this NVML
+    pub fn state(&self) -> Rc<RefCell<MockState>> {
+        self.0.clone()
+    }
+
+    fn with<T>(
+        &self,
+        dev: NvidiaDeviceHandle,
+        f: impl FnOnce(&mut MockDevice) -> Result<T, NvidiaError>,
+    ) -> Result<T, NvidiaError> {
+        let mut state = self.0.borrow_mut();
+        if !state.initialized {
+            return Err(error(ERROR_UNINITIALIZED));
+        }
+
+        let index = dev.0.as_ptr() as usize - 1;
+        let dev = state
+            .devices
+            .get_mut(index)
+            .ok_or(error(ERROR_INVALID_ARGUMENT))?;
+        if dev.lost {
+            return Err(error(ERROR_GPU_IS_LOST));
+        }
+
+        f(dev)
+    }
+
+    fn with_fan<T>(
+        &self,
+        dev: NvidiaDeviceHandle,
+        fan: u32,
+        f: impl FnOnce(&mut Option<u32>) -> T,
+    ) -> Result<T, NvidiaError> {
+        self.with(dev, |dev| {
+            let fan = dev
+                .fans
+                .get_mut(fan as usize)
+                .ok_or(error(ERROR_INVALID_ARGUMENT))?;
+            Ok(f(fan))
+        })
+    }
+}
+
+impl Nvml for MockNvml {
+    fn init(&self) -> Result<(), NvidiaError> {
+        let mut state = self.0.borrow_mut();
+        state.inits += 1;
+        if let Some(code) = state.init_error {
+            return Err(error(code));
+        }
+        state.initialized = true;
+
+        Ok(())
+    }
+
+    fn shutdown(&self) -> Result<(), NvidiaError> {
+        self.0.borrow_mut().initialized = false;
+
+        Ok(())
+    }
+
+    fn device_count(&self) -> Result<u32, NvidiaError> {
+        let state = self.0.borrow();
+        if !state.initialized {
+            return Err(error(ERROR_UNINITIALIZED));
+        }
+
+        Ok(state.devices.len() as u32)
+    }
+
+    fn device_by_index(&self, index: u32) -> Result<NvidiaDeviceHandle, NvidiaError> {
+        if index >= self.device_count()? {
+            return Err(error(ERROR_INVALID_ARGUMENT));
+        }
+
+        let handle = NonNull::new((index as usize + 1) as *mut ()).unwrap();
+        Ok(NvidiaDeviceHandle(handle))
+    }
+
+    fn device_by_pci_bus_id(&self, bus_id: &str) -> Result<NvidiaDeviceHandle, NvidiaError> {
+        let index = self
+            .0
+            .borrow()
+            .devices
+            .iter()
+            .position(|dev| dev.pci_bus_id.eq_ignore_ascii_case(bus_id))
+            .ok_or(error(ERROR_NOT_FOUND))?;
+
+        self.device_by_index(index as u32)
+    }
+
+    fn device_name(&self, dev: NvidiaDeviceHandle) -> Result<String, NvidiaError> {
+        self.with(dev, |dev| Ok(dev.name.clone()))
+    }
+
+    fn device_uuid(&self, dev: NvidiaDeviceHandle) -> Result<String, NvidiaError> {
+        self.with(dev, |dev| Ok(dev.uuid.clone()))
+    }
+
+    fn device_temperature(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        self.with(dev, |dev| Ok(dev.temperature))
+    }
+
+    fn device_field_value(
+        &self,
+        dev: NvidiaDeviceHandle,
+        field_id: u32,
+    ) -> Result<f64, NvidiaError> {
+        self.with(dev, |dev| match field_id {
+            FIELD_MEMORY_TEMP => dev.memory_temperature.ok_or(error(ERROR_NOT_SUPPORTED)),
+            _ => Err(error(ERROR_NOT_SUPPORTED)),
+        })
+    }
+
+    fn device_power_usage(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        self.with(dev, |dev| Ok(dev.power))
+    }
+
+    fn device_utilization(
+        &self,
+        dev: NvidiaDeviceHandle,
+    ) -> Result<NvidiaUtilization, NvidiaError> {
+        self.with(dev, |dev| Ok(dev.utilization))
+    }
+
+    fn device_fan_speed(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        self.with_fan(dev, 0, |fan| fan.unwrap_or(30))
+    }
+
+    fn device_clock_sm(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        self.with(dev, |dev| Ok(dev.clock_sm))
+    }
+
+    fn device_fan_count(&self, dev: NvidiaDeviceHandle) -> Result<u32, NvidiaError> {
+        self.with(dev, |dev| Ok(dev.fans.len() as u32))
+    }
+
+    fn device_min_max_fan_speed(&self, dev: NvidiaDeviceHandle) -> Result<(u32, u32), NvidiaError> {
+        self.with(dev, |dev| Ok(dev.min_max_fan_speed))
+    }
+
+    fn device_set_fan_speed(
+        &self,
+        dev: NvidiaDeviceHandle,
+        fan: u32,
+        speed: u32,
+    ) -> Result<(), NvidiaError> {
+        self.with_fan(dev, fan, |fan| *fan = Some(speed))
+    }
+
+    fn device_set_default_fan_speed(
+        &self,
+        dev: NvidiaDeviceHandle,
+        fan: u32,
+    ) -> Result<(), NvidiaError> {
+        self.with_fan(dev, fan, |fan| *fan = None)
+    }
+
+    fn error_string(&self, error: NvidiaError) -> String {
+        format!("mock error {}", error.0)
+    }
+}
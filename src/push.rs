@@ -0,0 +1,463 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// time given to HTTP client to send request
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+/// connection of unix socket is closed if no line is received for this time
+const UNIX_TIMEOUT: Duration = Duration::from_secs(60);
+/// max size of line of unix socket and of HTTP request line and headers
+const MAX_LINE: usize = 8 * 1024;
+/// max count of open connections of every listener
+const MAX_CONNECTIONS: usize = 16;
+const HTTP_MAX_BODY: usize = 64;
+/// header required in HTTP requests, browsers cannot send it to other sites without preflight
+const HTTP_PUSH_HEADER: &str = "x-fand-push";
+
+/// last pushed value and time when it was pushed
+type PushValue = Option<(f32, Instant)>;
+
+/// values of push sources shared with listeners
+#[derive(Clone, Default)]
+pub struct PushValues(Arc<Mutex<HashMap<String, PushValue>>>);
+
+/// unix socket of [`listen_unix`]. socket file is removed on drop
+pub struct PushSocket(PathBuf);
+
+/// count of open connections of listener
+#[derive(Default)]
+struct Connections(Arc<AtomicUsize>);
+
+/// open connection, it's uncounted on drop
+struct Connection(Arc<AtomicUsize>);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PushError {
+    #[error("unknown push source {0:?}")]
+    UnknownSource(String),
+    #[error("invalid value {0:?}")]
+    InvalidValue(String),
+}
+
+impl PushValues {
+    /// allow values to be pushed to source `name`
+    pub fn register(&self, name: &str) {
+        self.0.lock().unwrap().insert(name.to_string(), None);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    /// last pushed value of source `name`
+    pub fn get(&self, name: &str) -> PushValue {
+        self.0.lock().unwrap().get(name).copied().flatten()
+    }
+
+    pub fn set(&self, name: &str, value: &str) -> Result<(), PushError> {
+        let value: f32 = value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|value: &f32| value.is_finite())
+            .ok_or_else(|| PushError::InvalidValue(value.to_string()))?;
+
+        let mut values = self.0.lock().unwrap();
+        let slot = values
+            .get_mut(name)
+            .ok_or_else(|| PushError::UnknownSource(name.to_string()))?;
+        *slot = Some((value, Instant::now()));
+
+        Ok(())
+    }
+}
+
+impl Connections {
+    /// `None` if there are already `MAX_CONNECTIONS` open connections
+    fn open(&self) -> Option<Connection> {
+        self.0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < MAX_CONNECTIONS).then_some(count + 1)
+            })
+            .ok()?;
+
+        Some(Connection(self.0.clone()))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Drop for PushSocket {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.0) {
+            log::warn!("cannot remove {:?}: {err}", self.0);
+        }
+    }
+}
+
+/// accept lines `<name> <value>` on unix socket at `path`. every line is answered by `ok` or `error: ...`
+pub fn listen_unix(path: &Path, values: PushValues) -> io::Result<PushSocket> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{path:?} exists and is not socket"),
+            ));
+        }
+        // socket is left by previous run
+        if UnixStream::connect(path).is_err() {
+            fs::remove_file(path)?;
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    log::info!("Listening for pushed values on {path:?}");
+
+    let connections = Connections::default();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let Some(connection) = connections.open() else {
+                        log::warn!("too many push connections, closing new one");
+                        continue;
+                    };
+                    let values = values.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = serve_unix(stream, &values, UNIX_TIMEOUT) {
+                            log::warn!("push connection failed: {err}");
+                        }
+                        drop(connection);
+                    });
+                }
+                Err(err) => log::warn!("cannot accept push connection: {err}"),
+            }
+        }
+    });
+
+    Ok(PushSocket(path.to_path_buf()))
+}
+
+fn serve_unix(stream: UnixStream, values: &PushValues, timeout: Duration) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        let size = match (&mut reader).take(MAX_LINE as u64).read_line(&mut line) {
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                log::debug!("closing idle push connection");
+                return Ok(());
+            }
+            size => size?,
+        };
+        if size == 0 {
+            return Ok(());
+        }
+        if line.len() >= MAX_LINE && !line.ends_with('\n') {
+            writeln!(writer, "error: line is too long")?;
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = match line.trim().split_once(char::is_whitespace) {
+            Some((name, value)) => values.set(name, value),
+            None => Err(PushError::InvalidValue(line.trim().to_string())),
+        };
+        match result {
+            Ok(()) => writeln!(writer, "ok")?,
+            Err(err) => writeln!(writer, "error: {err}")?,
+        }
+    }
+}
+
+/// accept HTTP requests `POST /source/<name>` with value in body. returns bound address
+pub fn listen_http(addr: SocketAddr, values: PushValues) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    log::info!("Listening for pushed values on http://{addr}");
+
+    let connections = Connections::default();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let Some(connection) = connections.open() else {
+                        log::warn!("too many push connections, closing new one");
+                        continue;
+                    };
+                    let values = values.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = serve_http(stream, addr, &values) {
+                            log::warn!("push request failed: {err}");
+                        }
+                        drop(connection);
+                    });
+                }
+                Err(err) => log::warn!("cannot accept push connection: {err}"),
+            }
+        }
+    });
+
+    Ok(addr)
+}
+
+/// requests of browsers are rejected: they are sent with `Origin`, with `Host` of other site
+/// after DNS rebinding, or without custom header if they are cross-site
+fn serve_http(stream: TcpStream, addr: SocketAddr, values: &PushValues) -> io::Result<()> {
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let mut read_line = || -> io::Result<String> {
+        let mut line = String::new();
+        (&mut reader).take(MAX_LINE as u64).read_line(&mut line)?;
+        Ok(line.trim_end().to_string())
+    };
+
+    let request = read_line()?;
+    let mut content_length = 0;
+    let (mut origin, mut host, mut push_header) = (false, None, false);
+    loop {
+        let header = read_line()?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = header.split_once(':') {
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.parse().unwrap_or(usize::MAX),
+                "origin" => origin = true,
+                "host" => host = Some(value.to_string()),
+                HTTP_PUSH_HEADER => push_header = true,
+                _ => {}
+            }
+        }
+    }
+
+    let hosts = [addr.to_string(), format!("localhost:{}", addr.port())];
+    let host_allowed = host.is_some_and(|host| hosts.iter().any(|h| h.eq_ignore_ascii_case(&host)));
+    let forbidden = if origin {
+        Some("requests with Origin are not allowed".to_string())
+    } else if !host_allowed {
+        Some(format!("Host must be {}", hosts.join(" or ")))
+    } else if !push_header {
+        Some("X-Fand-Push header is required".to_string())
+    } else {
+        None
+    };
+
+    let mut parts = request.split_whitespace();
+    let (status, message) = match (forbidden, parts.next(), parts.next()) {
+        (Some(reason), _, _) => ("403 Forbidden", reason),
+        (None, Some("POST"), Some(path)) => match path.strip_prefix("/source/") {
+            None => ("404 Not Found", "not found".to_string()),
+            Some(_) if content_length > HTTP_MAX_BODY => {
+                ("413 Payload Too Large", "value is too long".to_string())
+            }
+            Some(name) => {
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body)?;
+                match values.set(name, &String::from_utf8_lossy(&body)) {
+                    Ok(()) => ("200 OK", "ok".to_string()),
+                    Err(err @ PushError::UnknownSource(_)) => ("404 Not Found", err.to_string()),
+                    Err(err) => ("400 Bad Request", err.to_string()),
+                }
+            }
+        },
+        (None, Some(_), Some(_)) => ("405 Method Not Allowed", "only POST is allowed".to_string()),
+        _ => ("400 Bad Request", "invalid request".to_string()),
+    };
+
+    write!(
+        writer,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}\n",
+        message.len() + 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{listen_http, listen_unix, serve_unix, PushError, PushValues, MAX_LINE};
+    use crate::test_dir::TestDir;
+    use std::{
+        fs,
+        io::{self, BufRead, BufReader, Read, Write},
+        net::TcpStream,
+        os::unix::net::UnixStream,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    fn value(values: &PushValues, name: &str) -> Option<f32> {
+        values.get(name).map(|(value, _)| value)
+    }
+
+    #[test]
+    fn set() {
+        let values = PushValues::default();
+        values.register("ambient");
+
+        assert_eq!(value(&values, "ambient"), None);
+        assert_eq!(values.set("ambient", " 23.5\n"), Ok(()));
+        assert_eq!(value(&values, "ambient"), Some(23.5));
+
+        assert_eq!(
+            values.set("ambient", "NaN"),
+            Err(PushError::InvalidValue("NaN".to_string()))
+        );
+        assert_eq!(
+            values.set("other", "1"),
+            Err(PushError::UnknownSource("other".to_string()))
+        );
+        assert_eq!(value(&values, "ambient"), Some(23.5));
+    }
+
+    #[test]
+    fn unix() {
+        let root = TestDir::new("push");
+        let path = root.join("push.sock");
+        let values = PushValues::default();
+        values.register("ambient");
+        let socket = listen_unix(&path, values.clone()).unwrap();
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut send = |line: &str| {
+            writeln!(&stream, "{line}").unwrap();
+            let mut answer = String::new();
+            reader.read_line(&mut answer).unwrap();
+            answer
+        };
+
+        assert_eq!(send("ambient 31"), "ok\n");
+        assert_eq!(value(&values, "ambient"), Some(31.0));
+        assert_eq!(send("ambient"), "error: invalid value \"ambient\"\n");
+        assert_eq!(send("cpu 40"), "error: unknown push source \"cpu\"\n");
+
+        // connection is closed after too long line, rest of line is not read
+        let long = format!("ambient {}", "1".repeat(MAX_LINE));
+        assert_eq!(send(&long), "error: line is too long\n");
+        let closed = reader.read_line(&mut String::new());
+        assert!(!matches!(closed, Ok(len) if len > 0));
+        assert_eq!(value(&values, "ambient"), Some(31.0));
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn unix_idle() {
+        let values = PushValues::default();
+        values.register("ambient");
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = thread::spawn({
+            let values = values.clone();
+            move || serve_unix(server, &values, Duration::from_millis(100))
+        });
+
+        let started = Instant::now();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        writeln!(&client, "ambient 31").unwrap();
+        let mut answer = String::new();
+        reader.read_line(&mut answer).unwrap();
+        assert_eq!(answer, "ok\n");
+
+        // connection is closed after timeout without lines
+        assert_eq!(reader.read_line(&mut String::new()).unwrap(), 0);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(server.join().unwrap().is_ok());
+        assert_eq!(value(&values, "ambient"), Some(31.0));
+    }
+
+    #[test]
+    fn unix_not_socket() {
+        let root = TestDir::new("push-file");
+        let path = root.write("", &[("push.sock", "data")]).join("push.sock");
+
+        let err = listen_unix(&path, PushValues::default()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+    }
+
+    #[test]
+    fn http() {
+        let values = PushValues::default();
+        values.register("ambient");
+        let addr = listen_http("127.0.0.1:0".parse().unwrap(), values.clone()).unwrap();
+
+        let request = |request: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response.lines().next().unwrap().to_string()
+        };
+        let post_with = |path: &str, headers: &str, body: &str| {
+            let len = body.len();
+            request(&format!(
+                "POST {path} HTTP/1.1\r\n{headers}Content-Length: {len}\r\n\r\n{body}"
+            ))
+        };
+        let headers = format!("Host: {addr}\r\nX-Fand-Push: 1\r\n");
+        let post = |path: &str, body: &str| post_with(path, &headers, body);
+
+        assert_eq!(post("/source/ambient", "27.25"), "HTTP/1.1 200 OK");
+        assert_eq!(value(&values, "ambient"), Some(27.25));
+        assert_eq!(post("/source/ambient", "hot"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(post("/source/cpu", "40"), "HTTP/1.1 404 Not Found");
+        assert_eq!(post("/metrics", "40"), "HTTP/1.1 404 Not Found");
+        assert_eq!(
+            request(&format!("GET /source/ambient HTTP/1.1\r\n{headers}\r\n")),
+            "HTTP/1.1 405 Method Not Allowed"
+        );
+
+        let localhost = format!("Host: localhost:{}\r\nX-Fand-Push: 1\r\n", addr.port());
+        assert_eq!(
+            post_with("/source/ambient", &localhost, "28"),
+            "HTTP/1.1 200 OK"
+        );
+        assert_eq!(value(&values, "ambient"), Some(28.0));
+
+        // requests which could be sent by browsers
+        let forbidden = [
+            format!("{headers}Origin: http://example.com\r\n"),
+            format!("Host: example.com:{}\r\nX-Fand-Push: 1\r\n", addr.port()),
+            "X-Fand-Push: 1\r\n".to_string(),
+            format!("Host: {addr}\r\nContent-Type: text/plain\r\n"),
+        ];
+        for headers in forbidden {
+            assert_eq!(
+                post_with("/source/ambient", &headers, "90"),
+                "HTTP/1.1 403 Forbidden",
+                "{headers}"
+            );
+        }
+        assert_eq!(value(&values, "ambient"), Some(28.0));
+    }
+}
@@ -1,13 +1,85 @@
-use signal_hook::{consts::TERM_SIGNALS, low_level::register as signal_handler};
-
-pub fn init() {
-    unsafe {
-        for &signal in TERM_SIGNALS {
-            signal_handler(signal, move || {
-                log::error!("Signal {signal} received. Panic!");
-                panic!()
-            })
-            .unwrap();
+use signal_hook::{consts::TERM_SIGNALS, flag, low_level::pipe};
+use std::{
+    io::Read,
+    os::unix::net::UnixStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// set by termination signals, checked by main loop
+pub struct Terminate {
+    flag: Arc<AtomicBool>,
+    /// receives byte for every signal, so sleep is interrupted
+    wake: UnixStream,
+}
+
+impl Terminate {
+    pub fn is_set(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    /// sleep for `duration` or until termination signal
+    pub fn sleep(&self, duration: Duration) {
+        if self.is_set() || duration.is_zero() {
+            return;
         }
+
+        if let Err(err) = self.wake.set_read_timeout(Some(duration)) {
+            log::error!("cannot wait for termination signal: {err}");
+            std::thread::sleep(duration);
+            return;
+        }
+        // returns after timeout, signal or interrupted read
+        let _ = (&self.wake).read(&mut [0; 16]);
+    }
+}
+
+/// first termination signal sets flag, second one terminates process
+pub fn init() -> Terminate {
+    let flag = Arc::new(AtomicBool::new(false));
+    let (wake, signaled) = UnixStream::pair().unwrap();
+    signaled.set_nonblocking(true).unwrap();
+    for &signal in TERM_SIGNALS {
+        flag::register_conditional_shutdown(signal, 1, flag.clone()).unwrap();
+        flag::register(signal, flag.clone()).unwrap();
+        pipe::register(signal, signaled.try_clone().unwrap()).unwrap();
+    }
+
+    Terminate { flag, wake }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::init;
+    use signal_hook::{consts::SIGTERM, low_level::raise};
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn sleep() {
+        let terminate = init();
+
+        let start = Instant::now();
+        terminate.sleep(Duration::from_millis(50));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(!terminate.is_set());
+
+        let signal = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(50));
+            raise(SIGTERM).unwrap();
+        });
+        let start = Instant::now();
+        terminate.sleep(Duration::from_secs(60));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(terminate.is_set());
+
+        // sleep returns at once after signal
+        terminate.sleep(Duration::from_secs(60));
+        signal.join().unwrap();
     }
 }
@@ -1,22 +1,42 @@
+mod amdgpu;
+mod command;
+mod drive;
 mod file;
+mod fixed;
 mod nvidia;
+mod push;
+mod thermal_zone;
 
 use std::{error::Error, fmt};
 
+pub use amdgpu::{AmdgpuMetric, SourceAmdgpu};
+pub use command::{CommandParser, SourceCommand};
+pub use drive::{DriveAggregate, DriveSelector, SourceDrive};
 pub use file::SourceFile;
-pub use nvidia::SourceNvidia;
+pub use fixed::SourceFixed;
+pub use nvidia::{NvidiaMetric, SourceNvidia};
+pub use push::SourcePush;
+pub use thermal_zone::SourceThermalZone;
 
-/// temperature
+/// temperature or other value of source. `unit` is used only for printing
 #[derive(Clone, Copy)]
-pub struct Temperature(f32);
+pub struct Temperature {
+    value: f32,
+    unit: &'static str,
+}
 
 impl Temperature {
     pub fn from_celsius(value: f32) -> Self {
-        Self(value)
+        Self::with_unit(value, "°C")
+    }
+
+    /// value which isn't temperature, e.g. watts or percents
+    pub fn with_unit(value: f32, unit: &'static str) -> Self {
+        Self { value, unit }
     }
 
     pub fn celsius(self) -> f32 {
-        self.0
+        self.value
     }
 }
 
@@ -30,11 +50,11 @@ impl fmt::Display for Temperature {
         let precision = f.precision().unwrap_or(2);
 
         let temp = if f.alternate() {
-            let value = self.0;
+            let value = self.value;
             format!("Temperature({value})")
         } else {
-            let value = self.0;
-            format!("{value:.precision$}°C")
+            let Self { value, unit } = self;
+            format!("{value:.precision$}{unit}")
         };
 
         if let Some(width) = f.width() {
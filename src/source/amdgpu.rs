@@ -0,0 +1,47 @@
+use super::{Source, SourceFile, Temperature};
+use crate::amdgpu::{AmdgpuCard, AmdgpuError};
+use std::error::Error;
+
+/// value read by [`SourceAmdgpu`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AmdgpuMetric {
+    /// celsius
+    Edge,
+    /// celsius
+    Junction,
+    /// celsius
+    Memory,
+    /// watts
+    PowerWatts,
+    /// percents
+    GpuBusyPercent,
+}
+
+/// sensor of amdgpu card read from its hwmon
+pub struct SourceAmdgpu {
+    file: SourceFile,
+}
+
+impl SourceAmdgpu {
+    pub fn new(card: &AmdgpuCard, metric: AmdgpuMetric) -> Result<Self, AmdgpuError> {
+        let (path, factor) = match metric {
+            AmdgpuMetric::Edge => (card.temperature("edge")?, 0.001),
+            AmdgpuMetric::Junction => (card.temperature("junction")?, 0.001),
+            AmdgpuMetric::Memory => (card.temperature("mem")?, 0.001),
+            AmdgpuMetric::PowerWatts => (card.power()?, 0.000_001),
+            AmdgpuMetric::GpuBusyPercent => (card.busy_percent()?, 1.0),
+        };
+
+        log::info!("Using {metric:?} of {card} from {path:?}");
+
+        let file = SourceFile::new(&path, Some(factor))?;
+
+        Ok(Self { file })
+    }
+}
+
+impl Source for SourceAmdgpu {
+    fn try_get_temperature(&self) -> Result<Temperature, Box<dyn Error>> {
+        self.file.try_get_temperature()
+    }
+}
@@ -0,0 +1,351 @@
+use super::{Source, Temperature};
+use regex::Regex;
+use std::{
+    error::Error,
+    io::{self, Read},
+    process::{Command, ExitStatus, Stdio},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// interval of checking whether command is exited
+const POLL: Duration = Duration::from_millis(5);
+
+/// error of source until first run of command is finished
+const NO_VALUE: &str = "no value yet";
+
+/// how value is found in output of command
+pub enum CommandParser {
+    /// whole output
+    Plain,
+    /// first capture group, or whole match if regex has no groups
+    Regex(Regex),
+    /// JSON pointer (e.g. `/gpus/0/temp`) into output parsed as JSON
+    JsonPointer(String),
+}
+
+/// value printed by command. command is run by background thread, so formulas only read
+/// last result and are not delayed by slow commands
+pub struct SourceCommand {
+    last: Arc<Mutex<Result<f32, String>>>,
+    /// background thread is stopped when it's dropped
+    _stop: mpsc::Sender<()>,
+}
+
+/// runs command and parses its output
+struct CommandRunner {
+    argv: Vec<String>,
+    parser: CommandParser,
+    timeout: Duration,
+}
+
+#[derive(Debug, Error)]
+pub enum SourceCommandError {
+    #[error("{0}")]
+    Io(io::Error),
+    #[error("terminated after {0:?}")]
+    Timeout(Duration),
+    #[error("{status}: {stderr}")]
+    Status { status: ExitStatus, stderr: String },
+    #[error("no value in output {0:?}")]
+    NoValue(String),
+    #[error("{0}")]
+    Json(serde_json::Error),
+}
+
+impl SourceCommand {
+    /// run command every `interval` in background thread. first run is started at once, but
+    /// source has no value until it's finished
+    pub fn new(
+        argv: Vec<String>,
+        parser: CommandParser,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Self {
+        let runner = CommandRunner {
+            argv,
+            parser,
+            timeout,
+        };
+        let last = Arc::new(Mutex::new(Err(NO_VALUE.to_string())));
+        let (stop, stopped) = mpsc::channel();
+
+        let thread_last = last.clone();
+        thread::spawn(move || loop {
+            let result = runner.try_get_value();
+            *thread_last.lock().unwrap() = result;
+
+            if !matches!(
+                stopped.recv_timeout(interval),
+                Err(mpsc::RecvTimeoutError::Timeout)
+            ) {
+                break;
+            }
+        });
+
+        Self { last, _stop: stop }
+    }
+}
+
+impl CommandRunner {
+    /// run command and return its stdout
+    fn run(&self) -> Result<String, SourceCommandError> {
+        let deadline = Instant::now() + self.timeout;
+
+        let mut child = Command::new(&self.argv[0])
+            .args(&self.argv[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = read_all(child.stdout.take().unwrap());
+        let stderr = read_all(child.stderr.take().unwrap());
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(SourceCommandError::Timeout(self.timeout));
+            }
+            thread::sleep(POLL);
+        };
+
+        // pipes could be kept open by children of command
+        let output = |pipe: mpsc::Receiver<io::Result<Vec<u8>>>| {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match pipe.recv_timeout(timeout) {
+                Ok(output) => Ok(String::from_utf8_lossy(&output?).into_owned()),
+                Err(_) => Err(SourceCommandError::Timeout(self.timeout)),
+            }
+        };
+
+        if !status.success() {
+            let stderr = output(stderr).unwrap_or_default();
+            return Err(SourceCommandError::Status {
+                status,
+                stderr: stderr.trim().to_string(),
+            });
+        }
+
+        output(stdout)
+    }
+
+    fn try_get_value(&self) -> Result<f32, String> {
+        let output = self.run().map_err(|err| err.to_string())?;
+        self.parser.parse(&output).map_err(|err| err.to_string())
+    }
+}
+
+impl CommandParser {
+    fn parse(&self, output: &str) -> Result<f32, SourceCommandError> {
+        let no_value = || SourceCommandError::NoValue(output.to_string());
+
+        match self {
+            CommandParser::Plain => output.trim().parse().map_err(|_| no_value()),
+            CommandParser::Regex(regex) => {
+                let captures = regex.captures(output).ok_or_else(no_value)?;
+                let found = captures.get(1).or_else(|| captures.get(0)).unwrap();
+                found.as_str().trim().parse().map_err(|_| no_value())
+            }
+            CommandParser::JsonPointer(pointer) => {
+                let json: serde_json::Value = serde_json::from_str(output)?;
+                match json.pointer(pointer).ok_or_else(no_value)? {
+                    serde_json::Value::Number(number) => number
+                        .as_f64()
+                        .map(|value| value as f32)
+                        .ok_or_else(no_value),
+                    serde_json::Value::String(string) => {
+                        string.trim().parse().map_err(|_| no_value())
+                    }
+                    _ => Err(no_value()),
+                }
+            }
+        }
+    }
+}
+
+/// read `pipe` to end in background thread
+fn read_all(mut pipe: impl Read + Send + 'static) -> mpsc::Receiver<io::Result<Vec<u8>>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = tx.send(pipe.read_to_end(&mut buf).map(|_| buf));
+    });
+
+    rx
+}
+
+impl From<io::Error> for SourceCommandError {
+    fn from(value: io::Error) -> Self {
+        SourceCommandError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for SourceCommandError {
+    fn from(value: serde_json::Error) -> Self {
+        SourceCommandError::Json(value)
+    }
+}
+
+impl Source for SourceCommand {
+    fn try_get_temperature(&self) -> Result<Temperature, Box<dyn Error>> {
+        let value = self.last.lock().unwrap().clone()?;
+        Ok(Temperature::from_celsius(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandParser, SourceCommand, NO_VALUE};
+    use crate::{source::Source, test_dir::TestDir};
+    use regex::Regex;
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    fn command(script: &str, parser: CommandParser) -> SourceCommand {
+        command_with(
+            script,
+            parser,
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+        )
+    }
+
+    fn command_with(
+        script: &str,
+        parser: CommandParser,
+        timeout: Duration,
+        interval: Duration,
+    ) -> SourceCommand {
+        let argv = ["sh", "-c", script].map(String::from).to_vec();
+        SourceCommand::new(argv, parser, timeout, interval)
+    }
+
+    fn read(source: &SourceCommand) -> Result<f32, String> {
+        source
+            .try_get_temperature()
+            .map(|temp| temp.celsius())
+            .map_err(|err| err.to_string())
+    }
+
+    /// value after first run of command is finished
+    fn value(source: &SourceCommand) -> Result<f32, String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let value = read(source);
+            if value != Err(NO_VALUE.to_string()) || Instant::now() >= deadline {
+                return value;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(value(&command("echo 42.5", CommandParser::Plain)), Ok(42.5));
+
+        let regex = Regex::new(r"temp: (\d+)").unwrap();
+        let source = command("echo 'fan: 1200\ntemp: 57 C'", CommandParser::Regex(regex));
+        assert_eq!(value(&source), Ok(57.0));
+
+        let regex = Regex::new(r"\d+").unwrap();
+        let source = command("echo 'temp 61C'", CommandParser::Regex(regex));
+        assert_eq!(value(&source), Ok(61.0));
+
+        let pointer = CommandParser::JsonPointer("/gpus/1/temp".to_string());
+        let source = command(
+            r#"echo '{"gpus": [{"temp": 40}, {"temp": 45.5}]}'"#,
+            pointer,
+        );
+        assert_eq!(value(&source), Ok(45.5));
+
+        let pointer = CommandParser::JsonPointer("/temp".to_string());
+        let source = command(r#"echo '{"temp": "38"}'"#, pointer);
+        assert_eq!(value(&source), Ok(38.0));
+
+        let pointer = CommandParser::JsonPointer("/missing".to_string());
+        let source = command(r#"echo '{"temp": 38}'"#, pointer);
+        assert!(value(&source)
+            .unwrap_err()
+            .starts_with("no value in output"));
+    }
+
+    #[test]
+    fn errors() {
+        let source = command("echo 'no sensor' >&2; exit 3", CommandParser::Plain);
+        assert_eq!(value(&source), Err("exit status: 3: no sensor".to_string()));
+
+        let source = command_with(
+            "sleep 5",
+            CommandParser::Plain,
+            Duration::from_millis(100),
+            Duration::from_secs(60),
+        );
+        assert_eq!(value(&source), Err("terminated after 100ms".to_string()));
+
+        let argv = vec!["/nonexistent/sensor".to_string()];
+        let source = SourceCommand::new(
+            argv,
+            CommandParser::Plain,
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+        );
+        assert!(value(&source).is_err());
+    }
+
+    #[test]
+    fn interval() {
+        let root = TestDir::new("command");
+        let path = root.join("runs");
+        let script = format!("echo x >> {0}; wc -l < {0}", path.display());
+
+        let source = command_with(
+            &script,
+            CommandParser::Plain,
+            Duration::from_secs(1),
+            Duration::from_millis(100),
+        );
+        // command is run at creation, then every interval
+        assert_eq!(value(&source), Ok(1.0));
+        assert_eq!(read(&source), Ok(1.0));
+
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(value(&source), Ok(2.0));
+
+        // command is not run after source is dropped
+        drop(source);
+        thread::sleep(Duration::from_millis(250));
+        let runs = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(runs.lines().count(), 2);
+    }
+
+    #[test]
+    fn slow_command() {
+        let started = Instant::now();
+        let source = command_with(
+            "sleep 0.2; echo 60",
+            CommandParser::Plain,
+            Duration::from_secs(1),
+            Duration::from_millis(50),
+        );
+        // creating and reading source doesn't wait for command
+        assert_eq!(read(&source), Err(NO_VALUE.to_string()));
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert_eq!(value(&source), Ok(60.0));
+
+        // last value is read while command is run again
+        for _ in 0..5 {
+            let started = Instant::now();
+            assert_eq!(read(&source), Ok(60.0));
+            assert!(started.elapsed() < Duration::from_millis(50));
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
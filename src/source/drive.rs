@@ -0,0 +1,278 @@
+use super::{Source, SourceFile, Temperature};
+use crate::hwmon;
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// links to block devices named by model and serial
+const BY_ID: &str = "/dev/disk/by-id";
+/// block devices in sysfs
+const BLOCK: &str = "/sys/block";
+
+/// how values of drive's sensors are combined
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DriveAggregate {
+    /// first sensor. `Composite` of NVMe drives
+    Composite,
+    Max,
+    Average,
+}
+
+/// drive selected by `/dev/disk/by-id` name or serial
+#[derive(Clone, Debug, PartialEq)]
+pub enum DriveSelector {
+    Id(String),
+    Serial(String),
+}
+
+/// temperature of drive reported by `drivetemp` or `nvme` hwmon
+pub struct SourceDrive {
+    sensors: Vec<SourceFile>,
+    aggregate: DriveAggregate,
+}
+
+#[derive(Debug, Error)]
+pub enum SourceDriveError {
+    #[error("{0}")]
+    Io(io::Error),
+    #[error("drive is not found by {0:?}")]
+    NotFound(DriveSelector),
+    #[error("{0} has no hwmon (is `drivetemp` module loaded?)")]
+    NoHwmon(String),
+    #[error("{0:?} has no temperature sensors")]
+    NoSensors(PathBuf),
+}
+
+impl SourceDrive {
+    pub fn new(
+        selector: &DriveSelector,
+        aggregate: DriveAggregate,
+    ) -> Result<Self, SourceDriveError> {
+        Self::new_in(Path::new(BY_ID), Path::new(BLOCK), selector, aggregate)
+    }
+
+    fn new_in(
+        by_id: &Path,
+        block: &Path,
+        selector: &DriveSelector,
+        aggregate: DriveAggregate,
+    ) -> Result<Self, SourceDriveError> {
+        let name = find_block(by_id, block, selector)?
+            .ok_or_else(|| SourceDriveError::NotFound(selector.clone()))?;
+
+        let hwmon = hwmon::find(&block.join(&name).join("device"))?
+            .ok_or_else(|| SourceDriveError::NoHwmon(name.clone()))?;
+
+        let mut temperatures = hwmon::temperatures(&hwmon)?;
+        if temperatures.is_empty() {
+            return Err(SourceDriveError::NoSensors(hwmon));
+        }
+        if aggregate == DriveAggregate::Composite {
+            temperatures.truncate(1);
+        }
+
+        let labels: Vec<_> = temperatures.iter().map(|(label, _)| label).collect();
+        log::info!("Using {aggregate:?} of {labels:?} of {name} from {hwmon:?}");
+
+        let sensors = temperatures
+            .iter()
+            .map(|(_, path)| SourceFile::new(path, Some(0.001)))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { sensors, aggregate })
+    }
+}
+
+/// name of block device (e.g. `sda` or `nvme0n1`) matching `selector`
+fn find_block(by_id: &Path, block: &Path, selector: &DriveSelector) -> io::Result<Option<String>> {
+    let device_name = |link: &Path| -> io::Result<Option<String>> {
+        let path = fs::canonicalize(link)?;
+        Ok(path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()))
+    };
+
+    match selector {
+        DriveSelector::Id(id) => match device_name(&by_id.join(id)) {
+            Ok(Some(name)) => find_disk(block, name),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            found => found,
+        },
+        DriveSelector::Serial(serial) => {
+            // names are like `ata-MODEL_SERIAL` and `nvme-MODEL_SERIAL`
+            let suffix = format!("_{serial}");
+            let mut names: Vec<_> = fs::read_dir(by_id)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<io::Result<_>>()?;
+            names.sort();
+            for name in names {
+                if name.to_string_lossy().ends_with(&suffix) {
+                    return device_name(&by_id.join(name));
+                }
+            }
+
+            // NVMe controllers report serial in sysfs
+            let mut names: Vec<_> = fs::read_dir(block)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<io::Result<_>>()?;
+            names.sort();
+            for name in names {
+                let path = block.join(&name).join("device/serial");
+                if fs::read_to_string(path).is_ok_and(|found| found.trim() == serial) {
+                    return Ok(Some(name.to_string_lossy().into_owned()));
+                }
+            }
+
+            Ok(None)
+        }
+    }
+}
+
+/// disk of partition (e.g. `sda` for `sda1`), `name` itself if it's disk
+fn find_disk(block: &Path, name: String) -> io::Result<Option<String>> {
+    if block.join(&name).exists() {
+        return Ok(Some(name));
+    }
+
+    // partitions are in directory of their disk
+    for entry in fs::read_dir(block)? {
+        let disk = entry?.file_name();
+        if block.join(&disk).join(&name).join("partition").exists() {
+            return Ok(Some(disk.to_string_lossy().into_owned()));
+        }
+    }
+
+    Ok(None)
+}
+
+impl From<io::Error> for SourceDriveError {
+    fn from(value: io::Error) -> Self {
+        SourceDriveError::Io(value)
+    }
+}
+
+impl Source for SourceDrive {
+    fn try_get_temperature(&self) -> Result<Temperature, Box<dyn Error>> {
+        let mut values = Vec::with_capacity(self.sensors.len());
+        for sensor in &self.sensors {
+            values.push(sensor.try_get_temperature()?.celsius());
+        }
+
+        let value = match self.aggregate {
+            DriveAggregate::Composite => values[0],
+            DriveAggregate::Max => values.iter().copied().fold(f32::MIN, f32::max),
+            DriveAggregate::Average => values.iter().sum::<f32>() / values.len() as f32,
+        };
+
+        Ok(Temperature::from_celsius(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DriveAggregate, DriveSelector, SourceDrive, SourceDriveError};
+    use crate::{source::Source, test_dir::TestDir};
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn drive() {
+        let root = TestDir::new("drive");
+        let (by_id, block) = (root.write("by-id", &[]), root.join("block"));
+        let dev = root.write(
+            "dev",
+            &[("sda", ""), ("sda1", ""), ("sdb", ""), ("nvme0n1", "")],
+        );
+
+        for (name, device) in [
+            ("ata-Samsung_SSD_860_EVO_500GB_S3Z2NB0K123456", "sda"),
+            ("ata-Samsung_SSD_860_EVO_500GB_S3Z2NB0K123456-part1", "sda1"),
+            ("ata-WDC_WD40EFRX-68N32N0_WD-WCC7K0000000", "sdb"),
+            ("nvme-Samsung_SSD_980_PRO_1TB_S5GXNF0R654321", "nvme0n1"),
+            ("nvme-eui.002538b000000000", "nvme0n1"),
+        ] {
+            symlink(dev.join(device), by_id.join(name)).unwrap();
+        }
+
+        root.write(
+            "block/sda/device/hwmon/hwmon3",
+            &[("temp1_input", "35000\n")],
+        );
+        root.write("block/sda/sda1", &[("partition", "1\n")]);
+        root.write("block/sdb/device", &[]);
+        root.write(
+            "block/nvme0n1/device",
+            &[("serial", "S5GXNF0R654321    \n")],
+        );
+        root.write(
+            "block/nvme0n1/device/hwmon2",
+            &[
+                ("temp1_input", "45000\n"),
+                ("temp1_label", "Composite\n"),
+                ("temp2_input", "50000\n"),
+                ("temp2_label", "Sensor 1\n"),
+                ("temp3_input", "40000\n"),
+                ("temp3_label", "Sensor 2\n"),
+            ],
+        );
+
+        let drive = |selector: DriveSelector, aggregate| {
+            SourceDrive::new_in(&by_id, &block, &selector, aggregate)
+        };
+        // rounded to millidegrees read from sysfs
+        let value = |selector, aggregate| {
+            let value = drive(selector, aggregate)
+                .unwrap()
+                .try_get_temperature()
+                .unwrap()
+                .celsius();
+            (value * 1000.0).round() / 1000.0
+        };
+        let id = |id: &str| DriveSelector::Id(id.to_string());
+        let serial = |serial: &str| DriveSelector::Serial(serial.to_string());
+
+        assert_eq!(
+            value(
+                id("ata-Samsung_SSD_860_EVO_500GB_S3Z2NB0K123456"),
+                DriveAggregate::Max
+            ),
+            35.0
+        );
+        // partition is resolved to its disk
+        assert_eq!(
+            value(
+                id("ata-Samsung_SSD_860_EVO_500GB_S3Z2NB0K123456-part1"),
+                DriveAggregate::Max
+            ),
+            35.0
+        );
+        assert_eq!(
+            value(serial("S3Z2NB0K123456"), DriveAggregate::Composite),
+            35.0
+        );
+
+        let nvme = id("nvme-Samsung_SSD_980_PRO_1TB_S5GXNF0R654321");
+        assert_eq!(value(nvme.clone(), DriveAggregate::Composite), 45.0);
+        assert_eq!(value(nvme.clone(), DriveAggregate::Max), 50.0);
+        assert_eq!(value(nvme, DriveAggregate::Average), 45.0);
+        assert_eq!(value(serial("S5GXNF0R654321"), DriveAggregate::Max), 50.0);
+
+        assert!(matches!(
+            drive(serial("S5GXNF0R000000"), DriveAggregate::Max),
+            Err(SourceDriveError::NotFound(_))
+        ));
+        assert!(matches!(
+            drive(id("ata-missing"), DriveAggregate::Max),
+            Err(SourceDriveError::NotFound(_))
+        ));
+        assert_eq!(
+            drive(serial("WD-WCC7K0000000"), DriveAggregate::Max)
+                .err()
+                .unwrap()
+                .to_string(),
+            "sdb has no hwmon (is `drivetemp` module loaded?)"
+        );
+    }
+}
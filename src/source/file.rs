@@ -53,12 +53,49 @@ impl SourceFile {
 
 impl Source for SourceFile {
     fn try_get_temperature(&self) -> Result<Temperature, Box<dyn Error>> {
-        let mut buf = [0u8; 10];
+        let mut buf = [0u8; 32];
         let size = self.file_read(&mut buf)?;
-        let buf = unsafe { std::slice::from_raw_parts(buf.as_ptr(), size - 1) };
-        let temp = unsafe { std::str::from_utf8_unchecked(&buf) };
-        let temp: u32 = temp.parse()?;
+        if size == buf.len() {
+            return Err(format!("value in {:?} is too long", self.file_path).into());
+        }
+        // values could be negative (e.g. thermal zones) and without trailing newline
+        let temp: i64 = std::str::from_utf8(&buf[..size])?.trim().parse()?;
 
         Ok(Temperature::from_celsius(temp as f32 * self.factor))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SourceFile;
+    use crate::{source::Source, test_dir::TestDir};
+
+    #[test]
+    fn parse() {
+        let root = TestDir::new("file");
+        let dir = root.write(
+            "",
+            &[
+                ("temp", "45000\n"),
+                ("negative", "-12500\n"),
+                ("no_newline", "45000"),
+                ("power", "1234567890\n"),
+                ("long", "12345678901234567890123456789012345\n"),
+                ("text", "hot\n"),
+            ],
+        );
+        let value = |name, factor| {
+            SourceFile::new(dir.join(name), factor)
+                .unwrap()
+                .try_get_temperature()
+                .map(|temp| (temp.celsius() * 1000.0).round() / 1000.0)
+        };
+
+        assert_eq!(value("temp", None).unwrap(), 45.0);
+        assert_eq!(value("negative", None).unwrap(), -12.5);
+        assert_eq!(value("no_newline", None).unwrap(), 45.0);
+        assert_eq!(value("power", Some(0.000001)).unwrap().round(), 1235.0);
+        assert!(value("long", None).is_err());
+        assert!(value("text", None).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+use super::{Source, Temperature};
+use std::{cell::Cell, error::Error};
+
+/// source with fixed value. used for testing formulas
+pub struct SourceFixed {
+    value: Cell<Option<Temperature>>,
+}
+
+impl SourceFixed {
+    /// source without value returns error
+    pub fn new(value: Option<f32>) -> Self {
+        Self {
+            value: Cell::new(value.map(Temperature::from_celsius)),
+        }
+    }
+
+    pub fn set(&self, value: Option<f32>) {
+        self.value.set(value.map(Temperature::from_celsius));
+    }
+}
+
+impl Source for SourceFixed {
+    fn try_get_temperature(&self) -> Result<Temperature, Box<dyn Error>> {
+        Ok(self.value.get().ok_or("source has no value in this case")?)
+    }
+}
@@ -0,0 +1,81 @@
+use super::{Source, Temperature};
+use crate::push::PushValues;
+use std::{error::Error, time::Duration};
+use thiserror::Error;
+
+/// value pushed by other process over socket or HTTP
+pub struct SourcePush {
+    values: PushValues,
+    name: String,
+    /// value older than this is error
+    max_age: Duration,
+}
+
+#[derive(Debug, Error)]
+pub enum SourcePushError {
+    #[error("no value is pushed yet")]
+    NoValue,
+    #[error("value is pushed {0:?} ago")]
+    Outdated(Duration),
+}
+
+impl SourcePush {
+    /// `name` must be registered in `values`
+    pub fn new(values: PushValues, name: String, max_age: Duration) -> Self {
+        Self {
+            values,
+            name,
+            max_age,
+        }
+    }
+}
+
+impl Source for SourcePush {
+    fn try_get_temperature(&self) -> Result<Temperature, Box<dyn Error>> {
+        let (value, time) = self
+            .values
+            .get(&self.name)
+            .ok_or(SourcePushError::NoValue)?;
+
+        let age = time.elapsed();
+        if age > self.max_age {
+            return Err(SourcePushError::Outdated(age).into());
+        }
+
+        Ok(Temperature::from_celsius(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourcePush;
+    use crate::{push::PushValues, source::Source};
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn max_age() {
+        let values = PushValues::default();
+        values.register("ambient");
+        let source = SourcePush::new(
+            values.clone(),
+            "ambient".to_string(),
+            Duration::from_millis(50),
+        );
+
+        let value = || {
+            source
+                .try_get_temperature()
+                .map(|temp| temp.celsius())
+                .map_err(|err| err.to_string())
+        };
+
+        assert_eq!(value(), Err("no value is pushed yet".to_string()));
+
+        values.set("ambient", "24").unwrap();
+        assert_eq!(value(), Ok(24.0));
+
+        thread::sleep(Duration::from_millis(100));
+        let err = value().unwrap_err();
+        assert!(err.starts_with("value is pushed"), "{err}");
+    }
+}
@@ -0,0 +1,184 @@
+use super::{Source, SourceFile, Temperature};
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// directory of thermal zones
+const THERMAL: &str = "/sys/class/thermal";
+
+/// temperature of thermal zone or its trip point
+pub struct SourceThermalZone {
+    file: SourceFile,
+}
+
+#[derive(Debug, Error)]
+pub enum SourceThermalZoneError {
+    #[error("{0}")]
+    Io(io::Error),
+    #[error("thermal zone {0:?} is not found")]
+    NotFound(String),
+    #[error("{path:?} has no {trip:?} trip point")]
+    NoTrip { path: PathBuf, trip: String },
+}
+
+impl SourceThermalZone {
+    /// first zone with `type` equal to `zone`. temperature of first trip point of type `trip` is
+    /// read instead of zone temperature if it's set
+    pub fn new(zone: &str, trip: Option<&str>) -> Result<Self, SourceThermalZoneError> {
+        Self::new_in(Path::new(THERMAL), zone, trip)
+    }
+
+    fn new_in(
+        thermal: &Path,
+        zone: &str,
+        trip: Option<&str>,
+    ) -> Result<Self, SourceThermalZoneError> {
+        let zones = find_zones(thermal, zone)?;
+        let Some(dir) = zones.first() else {
+            return Err(SourceThermalZoneError::NotFound(zone.to_string()));
+        };
+        if zones.len() > 1 {
+            log::warn!("thermal zone {zone:?} matches {zones:?}, using first one");
+        }
+
+        let path = match trip {
+            Some(trip) => find_trip(dir, trip)?.ok_or_else(|| SourceThermalZoneError::NoTrip {
+                path: dir.clone(),
+                trip: trip.to_string(),
+            })?,
+            None => dir.join("temp"),
+        };
+
+        log::info!("Using {path:?} of thermal zone {zone:?}");
+
+        let file = SourceFile::new(&path, Some(0.001))?;
+
+        Ok(Self { file })
+    }
+}
+
+/// `thermal_zoneN` directories with `type`, ordered by `N`
+fn find_zones(thermal: &Path, zone: &str) -> io::Result<Vec<PathBuf>> {
+    let mut zones = Vec::new();
+
+    for entry in fs::read_dir(thermal)? {
+        let name = entry?.file_name();
+        let Some(index) = name
+            .to_string_lossy()
+            .strip_prefix("thermal_zone")
+            .and_then(|index| index.parse::<u32>().ok())
+        else {
+            continue;
+        };
+
+        let dir = thermal.join(&name);
+        // zones which type cannot be read are skipped
+        let Ok(found) = fs::read_to_string(dir.join("type")) else {
+            continue;
+        };
+        if found.trim() == zone {
+            zones.push((index, dir));
+        }
+    }
+    zones.sort();
+
+    Ok(zones.into_iter().map(|(_, dir)| dir).collect())
+}
+
+/// `trip_point_N_temp` of first trip point with type `trip`
+fn find_trip(dir: &Path, trip: &str) -> io::Result<Option<PathBuf>> {
+    for index in 0.. {
+        let found = match fs::read_to_string(dir.join(format!("trip_point_{index}_type"))) {
+            Ok(found) => found,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        if found.trim() == trip {
+            return Ok(Some(dir.join(format!("trip_point_{index}_temp"))));
+        }
+    }
+
+    Ok(None)
+}
+
+impl From<io::Error> for SourceThermalZoneError {
+    fn from(value: io::Error) -> Self {
+        SourceThermalZoneError::Io(value)
+    }
+}
+
+impl Source for SourceThermalZone {
+    fn try_get_temperature(&self) -> Result<Temperature, Box<dyn Error>> {
+        self.file.try_get_temperature()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SourceThermalZone, SourceThermalZoneError};
+    use crate::{source::Source, test_dir::TestDir};
+
+    #[test]
+    fn thermal_zone() {
+        let root = TestDir::new("thermal");
+
+        for (zone, files) in [
+            (
+                "thermal_zone0",
+                vec![("type", "acpitz\n"), ("temp", "27000\n")],
+            ),
+            (
+                "thermal_zone1",
+                vec![
+                    ("type", "x86_pkg_temp\n"),
+                    ("temp", "52000\n"),
+                    ("trip_point_0_type", "passive\n"),
+                    ("trip_point_0_temp", "0\n"),
+                    ("trip_point_1_type", "critical\n"),
+                    ("trip_point_1_temp", "100000\n"),
+                ],
+            ),
+            (
+                "thermal_zone10",
+                vec![("type", "acpitz\n"), ("temp", "30000\n")],
+            ),
+            // zone without type is skipped
+            ("thermal_zone2", vec![("temp", "40000\n")]),
+            (
+                "thermal_zone3",
+                vec![("type", "soc_cold\n"), ("temp", "-5500\n")],
+            ),
+            ("cooling_device0", vec![("type", "Processor\n")]),
+        ] {
+            root.write(zone, &files);
+        }
+
+        // rounded to millidegrees read from sysfs
+        let value = |zone, trip| {
+            let value = SourceThermalZone::new_in(&root, zone, trip)
+                .unwrap()
+                .try_get_temperature()
+                .unwrap()
+                .celsius();
+            (value * 1000.0).round() / 1000.0
+        };
+
+        assert_eq!(value("x86_pkg_temp", None), 52.0);
+        assert_eq!(value("x86_pkg_temp", Some("critical")), 100.0);
+        assert_eq!(value("acpitz", None), 27.0);
+        assert_eq!(value("soc_cold", None), -5.5);
+
+        assert!(matches!(
+            SourceThermalZone::new_in(&root, "iwlwifi_1", None),
+            Err(SourceThermalZoneError::NotFound(_))
+        ));
+        assert!(matches!(
+            SourceThermalZone::new_in(&root, "acpitz", Some("critical")),
+            Err(SourceThermalZoneError::NoTrip { .. })
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// directory in [`std::env::temp_dir`] for tests. removed on drop, so failed test leaves no debris
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    /// empty directory `fand-{name}-{pid}`
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("fand-{name}-{}", std::process::id()));
+        // left by killed run with the same pid
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    /// create directory `dir` with `files` and return its path
+    pub fn write(&self, dir: impl AsRef<Path>, files: &[(&str, &str)]) -> PathBuf {
+        let dir = self.0.join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, value) in files {
+            fs::write(dir.join(name), value).unwrap();
+        }
+
+        dir
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{
+    computed::ComputeEngine,
+    config::Config,
+    source::{Source, SourceFixed},
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::Path,
+    rc::Rc,
+};
+use thiserror::Error;
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Cases {
+    #[serde(rename = "case")]
+    cases: Vec<Case>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Case {
+    name: Option<String>,
+    /// values of sources. other sources return error
+    #[serde(default)]
+    sources: HashMap<String, f32>,
+    /// expected power by fan name
+    expect: BTreeMap<String, f64>,
+    tolerance: Option<f64>,
+}
+
+#[derive(Debug, Error)]
+pub enum CasesReadError {
+    #[error("{0}")]
+    Io(io::Error),
+    #[error("{0}")]
+    Toml(toml::de::Error),
+    #[error("{0}")]
+    Json(serde_json::Error),
+}
+
+impl From<io::Error> for CasesReadError {
+    fn from(value: io::Error) -> Self {
+        CasesReadError::Io(value)
+    }
+}
+
+impl From<toml::de::Error> for CasesReadError {
+    fn from(value: toml::de::Error) -> Self {
+        CasesReadError::Toml(value)
+    }
+}
+
+impl From<serde_json::Error> for CasesReadError {
+    fn from(value: serde_json::Error) -> Self {
+        CasesReadError::Json(value)
+    }
+}
+
+/// read cases from `*.json` or TOML file
+fn read_cases(path: &Path) -> Result<Vec<Case>, CasesReadError> {
+    let text = fs::read_to_string(path)?;
+    let cases: Cases = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&text)?
+    } else {
+        toml::from_str(&text)?
+    };
+
+    Ok(cases.cases)
+}
+
+/// sources of config with values set by every case
+type FixedSources = HashMap<String, Rc<SourceFixed>>;
+
+/// run formulas of `config` for every case from `path`. returns `true` if all cases passed
+pub fn run(config: &Config, path: &Path, tolerance: f64) -> Result<bool, CasesReadError> {
+    let cases = read_cases(path)?;
+
+    let sources = fixed_sources(config);
+    let engine = match create_engine(config, &sources) {
+        Ok(engine) => engine,
+        Err(err) => {
+            println!("FAIL cannot create engine: {err:#}");
+            return Ok(false);
+        }
+    };
+
+    let mut failed = 0;
+    for (index, case) in cases.iter().enumerate() {
+        let name = case
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("case[{index}]"));
+        let tolerance = case.tolerance.unwrap_or(tolerance);
+        let failures = check(config, &engine, &sources, case, tolerance);
+
+        if failures.is_empty() {
+            println!("ok   {name}");
+        } else {
+            failed += 1;
+            println!("FAIL {name}");
+            for failure in failures {
+                println!("     {failure}");
+            }
+        }
+    }
+    println!("{} passed, {failed} failed", cases.len() - failed);
+
+    Ok(failed == 0)
+}
+
+fn fixed_sources(config: &Config) -> FixedSources {
+    config
+        .sources
+        .keys()
+        .map(|name| (name.clone(), Rc::new(SourceFixed::new(None))))
+        .collect()
+}
+
+/// one engine is used by all cases
+fn create_engine(
+    config: &Config,
+    sources: &FixedSources,
+) -> Result<ComputeEngine, deno_core::error::AnyError> {
+    let sources = sources
+        .iter()
+        .map(|(name, source)| (name.clone(), source.clone() as Rc<dyn Source>))
+        .collect();
+
+    crate::create_engine(
+        sources,
+        config.main.history,
+        &config.main.scripts,
+        config.shared.as_ref(),
+    )
+}
+
+/// returns descriptions of failed expectations
+fn check(
+    config: &Config,
+    engine: &ComputeEngine,
+    sources: &FixedSources,
+    case: &Case,
+    tolerance: f64,
+) -> Vec<String> {
+    let mut failures: Vec<_> = case
+        .sources
+        .keys()
+        .filter(|name| !sources.contains_key(*name))
+        .map(|name| format!("unknown source {name}"))
+        .collect();
+
+    for (name, source) in sources {
+        source.set(case.sources.get(name).copied());
+    }
+    // cases don't see history and helpers state of previous cases
+    engine.reset();
+
+    let mut results = BTreeMap::new();
+    for (index, fan) in config.fans.iter().enumerate() {
+        let name = fan.display_name(index);
+        if !case.expect.contains_key(&name) {
+            continue;
+        }
+
+        let result = engine
+            .create_computed(index, &name, &fan.value, fan.timeout)
+            .map_err(|err| err.to_string())
+            .and_then(|mut computed| {
+                computed
+                    .try_compute()
+                    .map(|power| power.fraction())
+                    .map_err(|err| err.to_string())
+            });
+        results.insert(name, result);
+    }
+
+    for (name, &expected) in &case.expect {
+        match results.get(name) {
+            None => failures.push(format!("{name}: unknown fan")),
+            Some(Err(err)) => {
+                failures.push(format!("{name}: expected {expected:.3}, got error: {err}"))
+            }
+            Some(Ok(power)) => {
+                let diff = power - expected;
+                if diff.abs() > tolerance {
+                    failures.push(format!(
+                        "{name}: expected {expected:.3} ± {tolerance}, got {power:.3} (diff {diff:+.3})"
+                    ));
+                }
+            }
+        }
+    }
+
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, create_engine, fixed_sources, run, Case, Cases};
+    use crate::{config::Config, test_dir::TestDir};
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn parse() {
+        const TOML: &str = r#"
+[[case]]
+name = "idle"
+sources = { cpu = 45, gpu = 70 }
+expect = { "fan[0]" = 0.55 }
+
+[[case]]
+expect = { cpu = 1 }
+tolerance = 0.1
+"#;
+        const JSON: &str = r#"{
+    "case": [
+        { "name": "idle", "sources": { "cpu": 45, "gpu": 70 }, "expect": { "fan[0]": 0.55 } },
+        { "expect": { "cpu": 1 }, "tolerance": 0.1 }
+    ]
+}"#;
+
+        let expected = Cases {
+            cases: vec![
+                Case {
+                    name: Some("idle".to_string()),
+                    sources: HashMap::from([("cpu".to_string(), 45.0), ("gpu".to_string(), 70.0)]),
+                    expect: BTreeMap::from([("fan[0]".to_string(), 0.55)]),
+                    tolerance: None,
+                },
+                Case {
+                    name: None,
+                    sources: HashMap::new(),
+                    expect: BTreeMap::from([("cpu".to_string(), 1.0)]),
+                    tolerance: Some(0.1),
+                },
+            ],
+        };
+
+        assert_eq!(toml::from_str::<Cases>(TOML).unwrap(), expected);
+        assert_eq!(serde_json::from_str::<Cases>(JSON).unwrap(), expected);
+    }
+
+    #[test]
+    fn cases() {
+        const CONF: &str = r#"
+[main]
+
+[source.cpu]
+type = "file"
+path = "/cpu"
+
+[[fan]]
+type = "pwm"
+path = "/pwm"
+value = "cpu / 100"
+
+[[fan]]
+name = "pump"
+type = "pwm"
+path = "/pwm2"
+value = "1"
+"#;
+        let config: Config = toml::from_str(CONF).unwrap();
+        let case = |sources: &[(&str, f32)], expect: &[(&str, f64)]| Case {
+            name: None,
+            sources: sources.iter().map(|&(k, v)| (k.to_string(), v)).collect(),
+            expect: expect.iter().map(|&(k, v)| (k.to_string(), v)).collect(),
+            tolerance: None,
+        };
+
+        let sources = fixed_sources(&config);
+        let engine = create_engine(&config, &sources).unwrap();
+        let check = |case: &Case| check(&config, &engine, &sources, case, 0.01);
+
+        let passed = case(&[("cpu", 50.0)], &[("fan[0]", 0.5), ("pump", 1.0)]);
+        assert_eq!(check(&passed), Vec::<String>::new());
+
+        let failed = case(&[("cpu", 50.0)], &[("fan[0]", 0.7)]);
+        assert_eq!(check(&failed).len(), 1);
+
+        // source without value is an error
+        let failed = case(&[], &[("fan[0]", 0.5)]);
+        assert_eq!(check(&failed).len(), 1);
+
+        let unknown = case(&[("gpu", 50.0)], &[("fan[2]", 0.5)]);
+        assert_eq!(
+            check(&unknown),
+            vec!["unknown source gpu", "fan[2]: unknown fan"]
+        );
+
+        let passed = case(&[("cpu", 25.0)], &[("fan[0]", 0.25)]);
+        assert_eq!(check(&passed), Vec::<String>::new());
+    }
+
+    #[test]
+    fn broken_script() {
+        let root = TestDir::new("test-formula");
+        let cases = root.write("", &[("cases.toml", "[[case]]\nexpect = { pump = 1 }\n")]);
+
+        let mut config: Config = toml::from_str(
+            r#"
+[main]
+
+[source.cpu]
+type = "file"
+path = "/cpu"
+
+[[fan]]
+name = "pump"
+type = "pwm"
+path = "/pwm"
+value = "1"
+"#,
+        )
+        .unwrap();
+        config.main.scripts = vec![root.join("missing.js")];
+
+        // error is reported instead of panic
+        assert!(!run(&config, &cases.join("cases.toml"), 0.01).unwrap());
+    }
+}